@@ -0,0 +1,320 @@
+use barnsley::util::Point;
+use egui::{Align2, Color32, FontId, Pos2, Sense, Stroke, Ui, Vec2};
+use rand::Rng;
+
+use crate::diagnostics::jacobian;
+use crate::ifs::IFS;
+use crate::render::{Accumulator, ChaosGame, RenderOptions};
+
+/// Steps a separate walk takes before its points count, to settle onto the attractor.
+const WARMUP: usize = 50;
+/// Steps of that walk used for the orbit statistics.
+const ORBIT_SAMPLES: usize = 2000;
+/// Number of halvings of the radius the correlation sum is taken over.
+const CORRELATION_SCALES: usize = 8;
+
+/// A straight line `y = slope * x + intercept` fitted by least squares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fit {
+    pub slope: f32,
+    pub intercept: f32,
+}
+
+impl Fit {
+    fn least_squares(points: &[(f32, f32)]) -> Option<Self> {
+        if points.len() < 2 {
+            return None;
+        }
+        let n = points.len() as f32;
+        let mean_x = points.iter().map(|p| p.0).sum::<f32>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f32>() / n;
+        let covariance: f32 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        let variance: f32 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        if variance <= 0.0 {
+            return None;
+        }
+        let slope = covariance / variance;
+        Some(Self {
+            slope,
+            intercept: mean_y - slope * mean_x,
+        })
+    }
+}
+
+/// Statistics of the attractor, from the last render and from a short walk of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// `(ln(1 / box size), ln(boxes hit))` for box sizes doubling from one pixel.
+    pub box_counts: Vec<(f32, f32)>,
+    /// The slope is the box-counting dimension.
+    pub box_fit: Option<Fit>,
+    pub correlation_dimension: Option<f32>,
+    /// Lower left and upper right corner of the plotted pixels, in chaos game coordinates.
+    pub bounds: Option<(Point, Point)>,
+    /// Density-weighted mean position of the plotted pixels.
+    pub centroid: Option<Point>,
+    /// Mean log growth of a tangent vector along the walk. Negative means nearby points converge.
+    pub lyapunov: Option<f32>,
+    /// Fraction of the canvas's pixels that were hit at all.
+    pub fill_ratio: f32,
+    /// Fraction of the walk's steps taken by each transform, indexed like `IFS::transforms`.
+    pub hit_share: Vec<f32>,
+}
+
+impl Analysis {
+    pub fn new(ifs: &IFS, options: &RenderOptions, accumulator: &Accumulator) -> Self {
+        let box_counts = box_counts(accumulator);
+        let box_fit = Fit::least_squares(&box_counts);
+        let (bounds, centroid) = bounds_and_centroid(accumulator);
+        let pixels = accumulator.width * accumulator.height;
+        let hit_pixels = accumulator.hits.iter().filter(|&&hits| hits > 0.0).count();
+
+        let mut analysis = Self {
+            box_counts,
+            box_fit,
+            correlation_dimension: None,
+            bounds,
+            centroid,
+            lyapunov: None,
+            fill_ratio: if pixels > 0 { hit_pixels as f32 / pixels as f32 } else { 0.0 },
+            hit_share: vec![0.0; ifs.transforms.len()],
+        };
+        if let Some(game) = ChaosGame::new(ifs, options) {
+            analysis.walk(&game);
+        }
+        analysis
+    }
+
+    /// Follows one walk, tracking a tangent vector for the Lyapunov exponent and keeping
+    /// the plotted points for the correlation dimension.
+    fn walk(&mut self, game: &ChaosGame<'_>) {
+        let mut rng = rand::thread_rng();
+        let random_point = |rng: &mut rand::rngs::ThreadRng| Point {
+            x: rng.gen_range(-1.0..=1.0),
+            y: rng.gen_range(-1.0..=1.0),
+        };
+        let mut point = random_point(&mut rng);
+        let mut tangent = (1.0_f32, 0.0_f32);
+        let mut previous = game.start(&mut rng);
+        let mut log_growth = 0.0;
+        let mut growth_steps = 0;
+        let mut counts = vec![0usize; self.hit_share.len()];
+        let mut samples = Vec::with_capacity(ORBIT_SAMPLES);
+
+        for step in 0..WARMUP + ORBIT_SAMPLES {
+            previous = game.next(previous, &mut rng);
+            let seed = game.seed(previous);
+            let [a, b, c, d] = jacobian(&game.ifs.transforms[seed], point);
+            let [ma, mb, mc, md] = game.matrix(previous);
+            point = game.apply(previous, point, &mut rng);
+            if !point.x.is_finite() || !point.y.is_finite() {
+                point = random_point(&mut rng);
+                continue;
+            }
+            if step < WARMUP {
+                continue;
+            }
+
+            // The symmetry element is applied after the seed transform, so it multiplies on the left.
+            let (tx, ty) = (a * tangent.0 + b * tangent.1, c * tangent.0 + d * tangent.1);
+            let (tx, ty) = (ma * tx + mb * ty, mc * tx + md * ty);
+            let length = tx.hypot(ty);
+            if length.is_finite() && length > 0.0 {
+                log_growth += length.ln();
+                growth_steps += 1;
+                tangent = (tx / length, ty / length);
+            }
+
+            counts[seed] += 1;
+            if game.plotted(previous) {
+                let plotted = game.plot_position(point, &mut rng);
+                if plotted.x.is_finite() && plotted.y.is_finite() {
+                    samples.push(plotted);
+                }
+            }
+        }
+
+        if growth_steps > 0 {
+            self.lyapunov = Some(log_growth / growth_steps as f32);
+        }
+        let total: usize = counts.iter().sum();
+        if total > 0 {
+            self.hit_share = counts.iter().map(|&count| count as f32 / total as f32).collect();
+        }
+        self.correlation_dimension = correlation_dimension(&samples);
+    }
+
+    pub fn ui(&self, ui: &mut Ui, labels: &[String]) {
+        let format_point = |point: &Point| format!("({:.3}, {:.3})", point.x, point.y);
+        egui::Grid::new("analysis").num_columns(2).striped(true).show(ui, |ui| {
+            ui.label("Box-counting dimension");
+            ui.label(self.box_fit.map_or("–".to_owned(), |fit| format!("{:.3}", fit.slope)));
+            ui.end_row();
+            ui.label("Correlation dimension");
+            ui.label(self.correlation_dimension.map_or("–".to_owned(), |d| format!("{d:.3}")));
+            ui.end_row();
+            ui.label("Lyapunov exponent");
+            ui.label(self.lyapunov.map_or("–".to_owned(), |l| format!("{l:.3}")));
+            ui.end_row();
+            ui.label("Bounding box");
+            ui.label(self.bounds.map_or("–".to_owned(), |(min, max)| {
+                format!("{} to {}", format_point(&min), format_point(&max))
+            }));
+            ui.end_row();
+            ui.label("Centroid");
+            ui.label(self.centroid.as_ref().map_or("–".to_owned(), format_point));
+            ui.end_row();
+            ui.label("Fill ratio");
+            ui.label(format!("{:.2}%", self.fill_ratio * 100.0));
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label("Share of chaos game steps per transform");
+        for (label, share) in labels.iter().zip(&self.hit_share) {
+            ui.add(egui::ProgressBar::new(*share).text(format!("{label}: {:.1}%", share * 100.0)));
+        }
+
+        ui.separator();
+        ui.label("Box counting: ln N(ε) against ln(1/ε)");
+        self.box_plot(ui);
+    }
+
+    /// The box counts as dots with the fitted line through them.
+    fn box_plot(&self, ui: &mut Ui) {
+        let (rect, _) = ui.allocate_exact_size(Vec2::new(ui.available_width(), 160.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+        if self.box_counts.len() < 2 {
+            return;
+        }
+        let (mut min_x, mut max_x, mut min_y, mut max_y) = (f32::MAX, f32::MIN, f32::MAX, f32::MIN);
+        for &(x, y) in &self.box_counts {
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+        }
+        let plot = rect.shrink(12.0);
+        let to_screen = |x: f32, y: f32| {
+            Pos2::new(
+                plot.left() + (x - min_x) / (max_x - min_x).max(f32::EPSILON) * plot.width(),
+                plot.bottom() - (y - min_y) / (max_y - min_y).max(f32::EPSILON) * plot.height(),
+            )
+        };
+        let color = ui.visuals().text_color();
+        if let Some(fit) = self.box_fit {
+            painter.line_segment(
+                [
+                    to_screen(min_x, fit.slope * min_x + fit.intercept),
+                    to_screen(max_x, fit.slope * max_x + fit.intercept),
+                ],
+                Stroke::new(1.0, Color32::from_rgb(220, 170, 40)),
+            );
+            painter.text(
+                plot.left_top(),
+                Align2::LEFT_TOP,
+                format!("slope {:.3}", fit.slope),
+                FontId::proportional(12.0),
+                color,
+            );
+        }
+        for &(x, y) in &self.box_counts {
+            painter.circle_filled(to_screen(x, y), 3.0, color);
+        }
+    }
+}
+
+/// Counts the boxes of the hit pixels at every power-of-two box size up to half the
+/// shorter side, with sizes measured in chaos game units.
+fn box_counts(accumulator: &Accumulator) -> Vec<(f32, f32)> {
+    let (width, height) = (accumulator.width, accumulator.height);
+    let unit_per_pixel = 2.0 / width.min(height).max(1) as f32;
+    let mut counts = Vec::new();
+    let mut size = 1;
+    while size <= width.min(height) / 2 {
+        let columns = (width - 1) / size + 1;
+        let rows = (height - 1) / size + 1;
+        let mut occupied = vec![false; columns * rows];
+        for ((row, col), &hits) in accumulator.hits.indexed_iter() {
+            if hits > 0.0 {
+                occupied[row / size * columns + col / size] = true;
+            }
+        }
+        let count = occupied.iter().filter(|&&hit| hit).count();
+        if count > 0 {
+            counts.push(((1.0 / (size as f32 * unit_per_pixel)).ln(), (count as f32).ln()));
+        }
+        size *= 2;
+    }
+    counts
+}
+
+fn bounds_and_centroid(accumulator: &Accumulator) -> (Option<(Point, Point)>, Option<Point>) {
+    let pixels_per_unit = accumulator.width.min(accumulator.height) as f32 / 2.0;
+    let to_world = |row: usize, col: usize| Point {
+        x: (col as f32 + 0.5 - accumulator.width as f32 / 2.0) / pixels_per_unit,
+        y: (accumulator.height as f32 / 2.0 - row as f32 - 0.5) / pixels_per_unit,
+    };
+    let mut bounds: Option<(Point, Point)> = None;
+    let (mut total, mut sum_x, mut sum_y) = (0.0, 0.0, 0.0);
+    for ((row, col), &hits) in accumulator.hits.indexed_iter() {
+        if hits <= 0.0 {
+            continue;
+        }
+        let point = to_world(row, col);
+        bounds = Some(match bounds {
+            None => (point, point),
+            Some((min, max)) => (
+                Point { x: min.x.min(point.x), y: min.y.min(point.y) },
+                Point { x: max.x.max(point.x), y: max.y.max(point.y) },
+            ),
+        });
+        total += hits;
+        sum_x += hits * point.x;
+        sum_y += hits * point.y;
+    }
+    let centroid = (total > 0.0).then(|| Point { x: sum_x / total, y: sum_y / total });
+    (bounds, centroid)
+}
+
+/// The slope of `ln C(r)` against `ln r`, where `C(r)` is the fraction of pairs of samples
+/// closer than `r`, over radii halving from the samples' extent.
+fn correlation_dimension(samples: &[Point]) -> Option<f32> {
+    if samples.len() < 2 {
+        return None;
+    }
+    let (mut min, mut max) = (samples[0], samples[0]);
+    for point in samples {
+        min = Point { x: min.x.min(point.x), y: min.y.min(point.y) };
+        max = Point { x: max.x.max(point.x), y: max.y.max(point.y) };
+    }
+    let extent = (max.x - min.x).hypot(max.y - min.y);
+    if extent <= 0.0 {
+        return None;
+    }
+    let radii: Vec<f32> = (1..=CORRELATION_SCALES)
+        .map(|k| extent / 2.0_f32.powi(k as i32))
+        .collect();
+    let mut counts = vec![0usize; radii.len()];
+    for (index, a) in samples.iter().enumerate() {
+        for b in &samples[index + 1..] {
+            let distance = (a.x - b.x).hypot(a.y - b.y);
+            for (count, &radius) in counts.iter_mut().zip(&radii) {
+                if distance < radius {
+                    *count += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+    let pairs = (samples.len() * (samples.len() - 1) / 2) as f32;
+    let points: Vec<(f32, f32)> = radii
+        .iter()
+        .zip(&counts)
+        .filter(|(_, &count)| count > 0)
+        .map(|(&radius, &count)| (radius.ln(), (count as f32 / pairs).ln()))
+        .collect();
+    Fit::least_squares(&points).map(|fit| fit.slope)
+}
@@ -1,14 +1,9 @@
 use std::sync::mpsc::{channel, Receiver, Sender};
 
-use barnsley::animation::AnimationSequence;
-use barnsley::config::{Config, ImageSettings, EvaluationSettings, self};
-use barnsley::ifs::IFS;
-use barnsley::image::Image;
-use barnsley::transform::{
-    AffineTransform, LinearTransform, Transform, Transformable, InverseJuliaTransform,
-};
+use barnsley::config::{ImageSettings, EvaluationSettings, self};
+use barnsley::transform::{AffineTransform, LinearTransform, InverseJuliaTransform};
 use barnsley::util::Color;
-use egui::{self, Ui, Vec2, FontId, RichText};
+use egui::{self, Color32, FontId, Rect, Rgba, RichText, Sense, Ui, Vec2};
 use egui_extras::install_image_loaders;
 use std::fs::{File, self};
 use std::future::Future;
@@ -17,19 +12,81 @@ use std::sync::Arc;
 use std::io::{Cursor, Write, Read};
 use strum::IntoEnumIterator;
 
-use crate::transform::Visualize;
+use crate::analysis::Analysis;
+use crate::aspect::AspectPreset;
+use crate::coloring::{Coloring, ColoringMode};
+use crate::diagnostics::{self, Contraction};
+use crate::export::{self, ExportFormat};
+use crate::filter::{DownsampleFilter, FilterSettings};
+use crate::histogram::Histograms;
+use crate::ifs::{AnimationSequence, IFS};
+use crate::julia::EscapeTime;
+use crate::overlay::{transform_overlay, trap_overlay, CanvasMapping};
+use crate::ranges::ParameterRanges;
+use crate::parameters::Parameters;
+use crate::preview::PreviewQuality;
+use crate::render::{self, Accumulator, RenderOptions};
+use crate::sweep::SweepTool;
+use crate::symmetry::{Symmetry, SymmetryKind};
+use crate::transform::{base_color, Transform, TransformInfo, VariationTransform, Visualize};
+use crate::transitions::TransitionMatrix;
+use crate::variation::Variation;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{fitting::Fitting, transform::Affine};
+
+/// Key the parameter ranges are saved under in eframe's storage.
+const PARAMETER_RANGES_KEY: &str = "parameter_ranges";
 
 // #[derive(PartialEq)]
 pub struct MyApp {
     animation_sequence: AnimationSequence,
-    rendered_image: Image,
+    rendered_image: Accumulator,
     num_points: usize,
     num_iterations: usize,
     width: usize,
     height: usize,
     selected_transform_to_add: Transform,
-    pub(crate) delete_triggered: bool,
-    pub(crate) transform_to_delete: usize,
+    selected_final_transform: Transform,
+    export_format: ExportFormat,
+    export_alpha: bool,
+    background_color: Color,
+    transparent_background: bool,
+    filter_settings: FilterSettings,
+    lock_aspect: bool,
+    /// Width divided by height, kept while `lock_aspect` is set.
+    aspect_ratio: f32,
+    show_overlay: bool,
+    symmetry: Symmetry,
+    coloring: Coloring,
+    show_trap: bool,
+    escape_time: EscapeTime,
+    preview: PreviewQuality,
+    /// Threads the chaos game runs on.
+    threads: usize,
+    /// Seed for the chaos game; the same seed and settings give the same image.
+    seed: u64,
+    /// Whether the image on screen is a preview that still needs a full-quality render.
+    showing_preview: bool,
+    /// The tone mapping divisor `rendered_image` was made for, also used when saving.
+    rendered_scale: usize,
+    transitions: TransitionMatrix,
+    /// Per-transform contraction of the edited system, updated with every render.
+    contractions: Vec<Contraction>,
+    show_analysis: bool,
+    /// Statistics of the last render, only kept up to date while the analysis window is open.
+    analysis: Option<Analysis>,
+    show_histograms: bool,
+    /// Density and color histograms of the last render, only kept while their window is open.
+    histograms: Option<Histograms>,
+    show_sweep: bool,
+    sweep_tool: SweepTool,
+    #[cfg(not(target_arch = "wasm32"))]
+    show_fitting: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    fitting: Fitting,
+    parameter_ranges: ParameterRanges,
+    transform_info: Vec<TransformInfo>,
+    pending_action: Option<(usize, TransformAction)>,
     pub(crate) rerender: bool,
     counter: u8,
     text_channel: (Sender<String>, Receiver<String>),
@@ -52,14 +109,45 @@ impl Default for MyApp {
                 ifs_vec,
                 step_counts: vec![2],
             },
-            rendered_image: Image::new(1024, 1024),
+            rendered_image: Accumulator::new(1024, 1024),
             num_points: 1000,
             num_iterations: 1000,
             width: 1024,
             height: 1024,
             selected_transform_to_add: Transform::AffineTransform(AffineTransform::default()),
-            delete_triggered: false,
-            transform_to_delete: 0,
+            selected_final_transform: Transform::VariationTransform(VariationTransform::new(Variation::Spherical)),
+            export_format: ExportFormat::Png8,
+            export_alpha: false,
+            background_color: Color { r: 0.0, g: 0.0, b: 0.0 },
+            transparent_background: false,
+            filter_settings: FilterSettings::default(),
+            lock_aspect: false,
+            aspect_ratio: 1.0,
+            show_overlay: false,
+            symmetry: Symmetry::default(),
+            coloring: Coloring::default(),
+            show_trap: true,
+            escape_time: EscapeTime::default(),
+            preview: PreviewQuality::default(),
+            threads: render::default_threads(),
+            seed: 0,
+            showing_preview: false,
+            rendered_scale: 1,
+            transitions: TransitionMatrix::default(),
+            contractions: Vec::new(),
+            show_analysis: false,
+            analysis: None,
+            show_histograms: false,
+            histograms: None,
+            show_sweep: false,
+            sweep_tool: SweepTool::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            show_fitting: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            fitting: Fitting::default(),
+            parameter_ranges: ParameterRanges::default(),
+            transform_info: Vec::new(),
+            pending_action: None,
             rerender: true,
             counter: 0,
             text_channel: channel(),
@@ -68,8 +156,183 @@ impl Default for MyApp {
 }
 
 impl MyApp {
+    /// Starts with the default document, restoring the settings saved by the last run.
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self::default();
+        if let Some(ranges) = cc.storage.and_then(|storage| eframe::get_value(storage, PARAMETER_RANGES_KEY)) {
+            app.parameter_ranges = ParameterRanges::restore(ranges);
+        }
+        app
+    }
+
+    fn render_image(&mut self) {
+        self.render(false);
+    }
+
+    /// Renders at the preview quality, or at full quality when `preview` is unset.
+    fn render(&mut self, preview: bool) {
+        let oversample = self.filter_settings.oversample;
+        let (width, height, num_points) = if preview {
+            (self.preview.side(self.width), self.preview.side(self.height), self.preview.points(self.num_points))
+        } else {
+            (self.width, self.height, self.num_points)
+        };
+        // How many hits make a pixel fully opaque: the average number of points per pixel.
+        let scale = 1.max((num_points * self.num_iterations) / (width * height));
+        self.transform_info.resize(self.animation_sequence.ifs_vec[0].len(), TransformInfo::default());
+        self.transitions.resize(self.animation_sequence.ifs_vec[0].len());
+        let options = RenderOptions {
+            flags: self.transform_info.iter().map(|info| info.flags).collect(),
+            symmetry: self.symmetry,
+            transitions: self.transitions.clone(),
+            coloring: self.coloring.clone(),
+            threads: self.threads,
+            seed: self.seed,
+        };
+        // The first frame is the system being edited, which the overlay and editors act on.
+        let ifs = render::system_at(&self.animation_sequence, 0);
+        let constant = self.escape_time.constant(&ifs).filter(|_| self.escape_time.enabled);
+        let accumulator = match constant {
+            Some(c) => self.escape_time.render(c, width * oversample, height * oversample, scale),
+            None => render::render(
+                &ifs,
+                &options,
+                width * oversample,
+                height * oversample,
+                self.num_iterations,
+                num_points,
+            ),
+        };
+        self.rendered_image = self.filter_settings.apply(accumulator);
+        self.rendered_scale = scale;
+        self.showing_preview = preview;
+        // The analysis is too slow to keep up with a drag, so it waits for the full render.
+        if !preview {
+            self.analysis = self
+                .show_analysis
+                .then(|| Analysis::new(&ifs, &options, &self.rendered_image));
+        }
+        self.contractions = self.animation_sequence.ifs_vec[0]
+            .transforms
+            .iter()
+            .map(Contraction::of)
+            .collect();
+        self.update_histograms();
+    }
+
+    /// Recomputes the histograms if their window is open; they also depend on the background.
+    fn update_histograms(&mut self) {
+        self.histograms = self
+            .show_histograms
+            .then(|| Histograms::new(&self.rendered_image, self.rendered_scale, self.background()));
+    }
+
+    /// Replaces the edited system with affine transforms found by the fitting tool, weighted by
+    /// the area they cover and keeping the existing colors where there are enough of them.
+    /// The other keyframes keep their transforms, truncated or padded with the fitted ones so
+    /// they stay the same length.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn apply_fit(&mut self, maps: &[Affine]) {
+        let ifs = &self.animation_sequence.ifs_vec[0];
+        let transforms: Vec<Transform> = maps
+            .iter()
+            .enumerate()
+            .map(|(index, map)| {
+                let base_color = ifs.transforms.get(index).map(base_color).unwrap_or_else(|| {
+                    let hue = index as f32 / maps.len() as f32;
+                    let [r, g, b] = egui::ecolor::Hsva::new(hue, 0.7, 0.9, 1.0).to_rgb();
+                    Color { r, g, b }
+                });
+                Transform::AffineTransform(AffineTransform {
+                    a: map.a,
+                    b: map.b,
+                    c: map.c,
+                    d: map.d,
+                    xshift: map.xshift,
+                    yshift: map.yshift,
+                    base_color,
+                    weight: (map.a * map.d - map.b * map.c).abs().max(0.01),
+                })
+            })
+            .collect();
+        for ifs in self.animation_sequence.ifs_vec[1..].iter_mut() {
+            ifs.transforms.truncate(transforms.len());
+            let kept = ifs.transforms.len();
+            ifs.transforms.extend_from_slice(&transforms[kept..]);
+        }
+        self.animation_sequence.ifs_vec[0].transforms = transforms;
+        self.transform_info.clear();
+        self.transform_info.resize(maps.len(), TransformInfo::default());
+        self.transitions.weights.clear();
+        self.rerender = true;
+    }
+
+    fn load_parameters(&mut self, parameters: Parameters) {
+        let Parameters { image_settings, evaluation_settings, transforms, final_transform, transform_info, symmetry, transitions, coloring, escape_time, seed } = parameters;
+        let mut ifs0: IFS = IFS::new();
+
+        for transform in transforms.into_iter() {
+            ifs0.add_transform(transform);
+        }
+        ifs0.final_transform = final_transform;
+        self.transform_info = transform_info;
+        self.transform_info.resize(ifs0.len(), TransformInfo::default());
+        self.animation_sequence.ifs_vec[0] = ifs0;
+        self.symmetry = symmetry;
+        self.transitions = transitions;
+        self.coloring = coloring;
+        self.escape_time = escape_time;
+        self.seed = seed;
+
+        let mut target_ifs = IFS::new();
+        target_ifs.add_transform(LinearTransform::new(0.07927406, 0.4419875, -0.64647937, 0.19174504,Color{r: 0.13267994, g: 0.49911928, b:0.9295654},0.93828845).into());
+        target_ifs.add_transform(InverseJuliaTransform::new(1.1700816, 2.9560707, Color{r: 0.9284186, g: 0.4638964, b:0.20791459}, 0.95615274).into());
+        target_ifs.add_transform(InverseJuliaTransform::new(1.0998807, 1.9877317, Color{r: 0.41831225, g: 0.5540522, b:0.46177816}, 1.0506994).into());
+
+        self.animation_sequence.ifs_vec[1] = target_ifs;
+        self.width = image_settings.width as usize;
+        self.height = image_settings.height as usize;
+        self.aspect_ratio = self.width as f32 / self.height as f32;
+        self.num_iterations = evaluation_settings.num_iterations as usize;
+        self.num_points = evaluation_settings.num_points as usize;
+        self.rerender = true;
+    }
+
+    fn parameters(&self) -> Parameters {
+        Parameters {
+            image_settings: ImageSettings{ 
+                width: self.width as u32,
+                height: self.height as u32,
+                path: "empty.png".into()
+            },
+            evaluation_settings: EvaluationSettings{
+                num_iterations: self.num_iterations as u32,
+                num_points: self.num_points as u32
+            },
+            transforms: self.animation_sequence.ifs_vec.get(0).unwrap().transforms.clone(),
+            final_transform: self.animation_sequence.ifs_vec[0].final_transform.clone(),
+            transform_info: self.transform_info.clone(),
+            symmetry: self.symmetry,
+            transitions: self.transitions.clone(),
+            coloring: self.coloring.clone(),
+            escape_time: self.escape_time.clone(),
+            seed: self.seed,
+        }
+    }
+
+    /// The color empty pixels are filled with, or `None` when they should stay transparent.
+    fn background(&self) -> Option<Color> {
+        if self.transparent_background {
+            None
+        } else {
+            Some(self.background_color)
+        }
+    }
+
     fn render_transform_ui(&mut self, ui: &mut Ui, index: usize) {
-        let show_delete = self.animation_sequence.ifs_vec.get(0).unwrap().len() > 1;
+        let transform_count = self.animation_sequence.ifs_vec.get(0).unwrap().len();
+        let show_delete = transform_count > 1;
+        self.transform_info.resize(transform_count, TransformInfo::default());
         for (transform_counter, transform) in &mut self
             .animation_sequence
             .ifs_vec
@@ -79,63 +342,189 @@ impl MyApp {
             .iter_mut()
             .enumerate()
         {
-            let (rerender_update, delete_trigger_update) = match transform {
-                Transform::LinearTransform(t) => t.ui(ui, format!("Linear: {transform_counter}"), show_delete),
-                Transform::AffineTransform(t) => t.ui(ui, format!("Affine: {transform_counter}"), show_delete),
-                Transform::MoebiusTransform(t) => t.ui(ui, format!("Moebius: {transform_counter}"), show_delete),
-                Transform::InverseJuliaTransform(t) => {
-                    t.ui(ui, format!("InverseJulia: {transform_counter}"), show_delete)
-                }
-            };
+            let info = &mut self.transform_info[transform_counter];
+            let label = info.label(transform, transform_counter);
+            let color = base_color(transform);
+            let id = ui.make_persistent_id(("transform", transform_counter));
 
-            self.rerender |= rerender_update;
-            self.delete_triggered |= delete_trigger_update;
+            let (_, header, body) = egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+                .show_header(ui, |ui| {
+                    let mut action = None;
+                    let (swatch, _) = ui.allocate_exact_size(Vec2::splat(12.0), Sense::hover());
+                    ui.painter().rect_filled(swatch, 2.0, Color32::from(Rgba::from_rgb(color.r, color.g, color.b)));
+                    let response = ui.label(label);
+                    if !info.note.is_empty() {
+                        response.on_hover_text(&info.note);
+                    }
+                    if let Some(contraction) = self.contractions.get(transform_counter) {
+                        let how = if contraction.exact { "exact" } else { "estimated on [-1, 1]²" };
+                        ui.colored_label(contraction.color(), contraction.label())
+                            .on_hover_text(format!("Largest factor by which this transform stretches distances ({how}). Below 1 it contracts."));
+                    }
+                    let flags = &mut info.flags;
+                    let mut flags_changed = ui.toggle_value(&mut flags.muted, "M").on_hover_text("Mute: leave out of the chaos game").changed();
+                    flags_changed |= ui.toggle_value(&mut flags.soloed, "S").on_hover_text("Solo: only plot points from soloed transforms").changed();
+                    if ui.add_enabled(transform_counter > 0, egui::Button::new("⏶")).on_hover_text("Move up").clicked() {
+                        action = Some(TransformAction::MoveUp);
+                    }
+                    if ui.add_enabled(transform_counter + 1 < transform_count, egui::Button::new("⏷")).on_hover_text("Move down").clicked() {
+                        action = Some(TransformAction::MoveDown);
+                    }
+                    if ui.button("Duplicate").clicked() {
+                        action = Some(TransformAction::Duplicate);
+                    }
+                    if show_delete && ui.button("Delete").clicked() {
+                        action = Some(TransformAction::Delete);
+                    }
+                    (flags_changed, action)
+                })
+                .body(|ui| {
+                    info.ui(ui);
+                    transform.ui(ui, &self.parameter_ranges)
+                });
 
-            if delete_trigger_update {
-                self.transform_to_delete = transform_counter;
+            let (flags_changed, action) = header.inner;
+            self.rerender |= flags_changed || body.map_or(false, |body| body.inner);
+            if let Some(action) = action {
+                self.pending_action = Some((transform_counter, action));
             }
         }
     }
+
+    /// The final transform's editor, or a picker to add one to every system in the sequence.
+    fn render_final_transform_ui(&mut self, ui: &mut Ui) {
+        ui.label("Applied to every point as it is plotted, without changing where the chaos game goes next.");
+        let final_transform = &mut self.animation_sequence.ifs_vec[0].final_transform;
+        match final_transform {
+            Some(transform) => {
+                ui.label(transform.get_name());
+                if transform.ui(ui, &self.parameter_ranges) {
+                    self.rerender = true;
+                }
+                if ui.button("Remove final transform").clicked() {
+                    for ifs in self.animation_sequence.ifs_vec.iter_mut() {
+                        ifs.final_transform = None;
+                    }
+                    self.rerender = true;
+                }
+            }
+            None => {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("final_transform_to_add")
+                        .selected_text(self.selected_final_transform.get_name())
+                        .show_ui(ui, |ui| {
+                            for t in Transform::kinds() {
+                                ui.selectable_value(
+                                    &mut self.selected_final_transform,
+                                    t.clone(),
+                                    t.get_name(),
+                                );
+                            }
+                        });
+                    if ui.button("Add final transform").clicked() {
+                        for ifs in self.animation_sequence.ifs_vec.iter_mut() {
+                            ifs.final_transform = Some(self.selected_final_transform.clone());
+                        }
+                        self.rerender = true;
+                    }
+                });
+            }
+        }
+    }
+
+    /// Applies a transform action to every system in the sequence so they stay aligned. Other
+    /// keyframes can be shorter than the edited system; those only change where they have `index`.
+    fn apply_transform_action(&mut self, index: usize, action: TransformAction) {
+        match action {
+            TransformAction::Delete => {
+                // cannot delete since there's only one transform left
+                if self.animation_sequence.ifs_vec.get(0).unwrap().len() <= 1 {
+                    return;
+                }
+                for ifs in self.animation_sequence.ifs_vec.iter_mut() {
+                    if index < ifs.len() {
+                        ifs.delete_transform(index);
+                    }
+                }
+                self.transform_info.remove(index);
+                self.transitions.delete(index);
+            }
+            TransformAction::Duplicate => {
+                for ifs in self.animation_sequence.ifs_vec.iter_mut() {
+                    if let Some(transform) = ifs.transforms.get(index).cloned() {
+                        ifs.add_transform(transform);
+                        ifs.transforms[index + 1..].rotate_right(1);
+                    }
+                }
+                self.transform_info.insert(index + 1, self.transform_info[index].clone());
+                self.transitions.duplicate(index);
+            }
+            TransformAction::MoveUp | TransformAction::MoveDown => {
+                let other = if action == TransformAction::MoveUp { index - 1 } else { index + 1 };
+                for ifs in self.animation_sequence.ifs_vec.iter_mut() {
+                    if index.max(other) < ifs.transforms.len() {
+                        ifs.transforms.swap(index, other);
+                    }
+                }
+                self.transform_info.swap(index, other);
+                self.transitions.swap(index, other);
+            }
+        }
+        self.render_image();
+    }
 }
 
-use image::RgbImage;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransformAction {
+    Delete,
+    Duplicate,
+    MoveUp,
+    MoveDown,
+}
+
+use image::RgbaImage;
 use ndarray::Array3;
 
-fn array_to_image(arr: Array3<u8>) -> RgbImage {
+/// Paints the usual grey checkerboard that marks transparent regions.
+fn paint_checkerboard(ui: &Ui, rect: Rect) {
+    const SQUARE: f32 = 16.0;
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(204));
+    let columns = (rect.width() / SQUARE).ceil() as usize;
+    let rows = (rect.height() / SQUARE).ceil() as usize;
+    for row in 0..rows {
+        for col in (row % 2..columns).step_by(2) {
+            let min = rect.min + Vec2::new(col as f32, row as f32) * SQUARE;
+            painter.rect_filled(
+                Rect::from_min_size(min, Vec2::splat(SQUARE)),
+                0.0,
+                Color32::from_gray(153),
+            );
+        }
+    }
+}
+
+fn array_to_image(arr: Array3<u8>) -> RgbaImage {
     assert!(arr.is_standard_layout());
 
     let (height, width, _) = arr.dim();
     let raw = arr.into_raw_vec();
 
-    RgbImage::from_raw(width as u32, height as u32, raw)
+    RgbaImage::from_raw(width as u32, height as u32, raw)
         .expect("container should have the right size for the image dimensions")
 }
 
 impl eframe::App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, PARAMETER_RANGES_KEY, &self.parameter_ranges);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
         install_image_loaders(ctx);
 
         if let Ok(text) = self.text_channel.1.try_recv() {
-            let config: Config = serde_json::from_str(&text).unwrap();
-
-            let mut ifs0: IFS = IFS::new();
-
-            for transform in config.transforms.into_iter() {
-                ifs0.add_transform(transform);
-            }
-            self.animation_sequence.ifs_vec[0] = ifs0;
-
-            let mut target_ifs = IFS::new();
-            target_ifs.add_transform(LinearTransform::new(0.07927406, 0.4419875, -0.64647937, 0.19174504,Color{r: 0.13267994, g: 0.49911928, b:0.9295654},0.93828845).into());
-            target_ifs.add_transform(InverseJuliaTransform::new(1.1700816, 2.9560707, Color{r: 0.9284186, g: 0.4638964, b:0.20791459}, 0.95615274).into());
-            target_ifs.add_transform(InverseJuliaTransform::new(1.0998807, 1.9877317, Color{r: 0.41831225, g: 0.5540522, b:0.46177816}, 1.0506994).into());
-
-            self.animation_sequence.ifs_vec[1] = target_ifs;
-            self.width = config.image_settings.width as usize;
-            self.height = config.image_settings.height as usize;
-            self.num_iterations = config.evaluation_settings.num_iterations as usize;
-            self.num_points = config.evaluation_settings.num_points as usize;
-            self.rerender = true;
+            let parameters: Parameters = serde_json::from_str(&text).unwrap();
+            self.load_parameters(parameters);
         }
 
         egui::SidePanel::left("controls")
@@ -158,15 +547,17 @@ impl eframe::App for MyApp {
                     
                     self.animation_sequence.ifs_vec.get_mut(0).unwrap().randomize();
 
-                    self.rendered_image = self.animation_sequence.animate_single_step(
-                        self.width,
-                        self.height,
-                        self.num_iterations,
-                        self.num_points,
-                        1,
-                    );
+                    self.render_image();
                 };
 
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Fit to image").clicked() {
+                    self.show_fitting = true;
+                }
+                if ui.button("Parameter sweep").clicked() {
+                    self.show_sweep = true;
+                }
+
                 #[cfg(not(target_arch = "wasm32"))]
                 if ui.button("Open parameters").clicked() {
                     let path = rfd::FileDialog::new().add_filter("json", &["json"]).pick_file().unwrap(); 
@@ -174,65 +565,35 @@ impl eframe::App for MyApp {
                     let mut file = File::open(path).unwrap();
                     let mut data = String::new();
                     file.read_to_string(&mut data).unwrap();
-                    let config: Config = serde_json::from_str(&data).unwrap();
-
-                    let mut ifs0: IFS = IFS::new();
-
-                    for transform in config.transforms.into_iter() {
-                        ifs0.add_transform(transform);
-                    }
-                    self.animation_sequence.ifs_vec[0] = ifs0;
-
-                    let mut target_ifs = IFS::new();
-                    target_ifs.add_transform(LinearTransform::new(0.07927406, 0.4419875, -0.64647937, 0.19174504,Color{r: 0.13267994, g: 0.49911928, b:0.9295654},0.93828845).into());
-                    target_ifs.add_transform(InverseJuliaTransform::new(1.1700816, 2.9560707, Color{r: 0.9284186, g: 0.4638964, b:0.20791459}, 0.95615274).into());
-                    target_ifs.add_transform(InverseJuliaTransform::new(1.0998807, 1.9877317, Color{r: 0.41831225, g: 0.5540522, b:0.46177816}, 1.0506994).into());
-
-                    self.animation_sequence.ifs_vec[1] = target_ifs;
-                    self.width = config.image_settings.width as usize;
-                    self.height = config.image_settings.height as usize;
-                    self.num_iterations = config.evaluation_settings.num_iterations as usize;
-                    self.num_points = config.evaluation_settings.num_points as usize;
-                    self.rerender = true;
+                    let parameters: Parameters = serde_json::from_str(&data).unwrap();
+                    self.load_parameters(parameters);
                 }
 
                 #[cfg(not(target_arch = "wasm32"))]
                 if ui.button("Save image").clicked() {
-                    let mut bytes: Vec<u8> = Vec::new();
-                    let save_scale =
-                        1.max((self.num_points * self.num_iterations) / (self.width * self.height));
-                    let buffer = array_to_image(self.rendered_image.to_u8(save_scale));
-                    let _ = buffer.write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Png);
+                    let bytes = export::encode(
+                        &self.rendered_image,
+                        self.rendered_scale,
+                        self.export_format,
+                        self.export_alpha || self.transparent_background,
+                        self.background_color,
+                    ).unwrap();
 
-                    let file = rfd::FileDialog::new().add_filter("png", &["png"]).save_file().unwrap(); 
+                    let extension = self.export_format.extension();
+                    let file = rfd::FileDialog::new().add_filter(extension, &[extension]).save_file().unwrap(); 
                     
-                    let _ = image::save_buffer(file.to_str().unwrap(), 
-                        &buffer, 
-                        self.height as u32, 
-                        self.width as u32, 
-                        image::ColorType::Rgb8);
+                    fs::write(file, bytes).unwrap();
                 }
 
                 #[cfg(not(target_arch = "wasm32"))]
                 if ui.button("Save parameters").clicked() {
                     let path = rfd::FileDialog::new().add_filter("json", &["json"]).save_file().unwrap(); 
                     
-                    let config = Config {
-                        image_settings: ImageSettings{ 
-                            width: self.width as u32,
-                            height: self.height as u32,
-                            path: "empty.png".into()
-                        },
-                        evaluation_settings: EvaluationSettings{
-                            num_iterations: self.num_iterations as u32,
-                            num_points: self.num_points as u32
-                        },
-                        transforms: self.animation_sequence.ifs_vec.get(0).unwrap().transforms.clone()
-                    };
+                    let parameters = self.parameters();
 
                 
                     let _ = File::create(path.to_str().unwrap()).unwrap();
-                    fs::write(path.to_str().unwrap(), serde_json::to_string(&config).unwrap()).unwrap();
+                    fs::write(path.to_str().unwrap(), serde_json::to_string(&parameters).unwrap()).unwrap();
                 }
 
                 #[cfg(target_arch = "wasm32")]
@@ -280,23 +641,26 @@ impl eframe::App for MyApp {
                     // target_ifs.add_transform(InverseJuliaTransform::new(1.0998807, 1.9877317, Color{r: 0.41831225, g: 0.5540522, b:0.46177816}, 1.0506994).into());
 
                     // self.animation_sequence.ifs_vec[1] = target_ifs;
-                    // self.width = config.image_settings.width as usize;
-                    // self.height = config.image_settings.height as usize;
-                    // self.num_iterations = config.evaluation_settings.num_iterations as usize;
-                    // self.num_points = config.evaluation_settings.num_points as usize;
+                    // self.width = image_settings.width as usize;
+                    // self.height = image_settings.height as usize;
+                    // self.num_iterations = evaluation_settings.num_iterations as usize;
+                    // self.num_points = evaluation_settings.num_points as usize;
                     // self.rerender = true;
                 }
 
                 #[cfg(target_arch = "wasm32")]
                 if ui.button("Save image").clicked() {
-                    let mut bytes: Vec<u8> = Vec::new();
-                    let save_scale =
-                        1.max((self.num_points * self.num_iterations) / (self.width * self.height));
-                    let buffer = array_to_image(self.rendered_image.to_u8(save_scale));
-                    let _ = buffer.write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Png);
+                    let bytes = export::encode(
+                        &self.rendered_image,
+                        self.rendered_scale,
+                        self.export_format,
+                        self.export_alpha || self.transparent_background,
+                        self.background_color,
+                    ).unwrap();
                     
+                    let extension = self.export_format.extension();
                     let future = async move {
-                        let file = rfd::AsyncFileDialog::new().add_filter("png", &["png"]).save_file().await;
+                        let file = rfd::AsyncFileDialog::new().add_filter(extension, &[extension]).save_file().await;
                         file.unwrap().write(&bytes).await
                     };
                     let data = async_std::task::block_on(future);
@@ -305,42 +669,66 @@ impl eframe::App for MyApp {
 
                 #[cfg(target_arch = "wasm32")]
                 if ui.button("Save parameters").clicked() {                    
-                    let config = Config {
-                        image_settings: ImageSettings{ 
-                            width: self.width as u32,
-                            height: self.height as u32,
-                            path: "empty.png".into()
-                        },
-                        evaluation_settings: EvaluationSettings{
-                            num_iterations: self.num_iterations as u32,
-                            num_points: self.num_points as u32
-                        },
-                        transforms: self.animation_sequence.ifs_vec.get(0).unwrap().transforms.clone()
-                    };
+                    let parameters = self.parameters();
 
                     let future = async move {
                         let file = rfd::AsyncFileDialog::new().add_filter("json", &["json"]).save_file().await;
-                        file.unwrap().write(&serde_json::to_vec(&config).unwrap().as_slice()).await
+                        file.unwrap().write(&serde_json::to_vec(&parameters).unwrap().as_slice()).await
                     };
                     let data = async_std::task::block_on(future);
                     ui.close_menu();
             
                 }
 
+                egui::ComboBox::from_label("Image format")
+                    .selected_text(self.export_format.get_name())
+                    .show_ui(ui, |ui| {
+                        for format in ExportFormat::iter() {
+                            ui.selectable_value(&mut self.export_format, format, format.get_name());
+                        }
+                    });
+                ui.add_enabled(
+                    self.export_format.supports_alpha(),
+                    egui::Checkbox::new(&mut self.export_alpha, "Include alpha from density"),
+                );
+
                 ui.separator();
                 ui.heading("Generation controls");
                 if ui
-                    .add(egui::Slider::new(&mut self.width, 1..=4096).text("Height"))
+                    .add(egui::Slider::new(&mut self.width, 1..=4096).text("Width"))
                     .changed()
                 {
+                    if self.lock_aspect {
+                        self.height = ((self.width as f32 / self.aspect_ratio).round() as usize).clamp(1, 4096);
+                    }
                     self.rerender = true;
                 }
                 if ui
-                    .add(egui::Slider::new(&mut self.height, 1..=4096).text("Width"))
+                    .add(egui::Slider::new(&mut self.height, 1..=4096).text("Height"))
                     .changed()
                 {
+                    if self.lock_aspect {
+                        self.width = ((self.height as f32 * self.aspect_ratio).round() as usize).clamp(1, 4096);
+                    }
                     self.rerender = true;
                 }
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut self.lock_aspect, "Lock aspect ratio").changed() {
+                        self.aspect_ratio = self.width as f32 / self.height as f32;
+                    }
+                    egui::ComboBox::from_id_source("aspect_preset")
+                        .selected_text("Presets")
+                        .show_ui(ui, |ui| {
+                            for preset in AspectPreset::iter() {
+                                if ui.selectable_label(false, preset.get_name()).clicked() {
+                                    self.lock_aspect = true;
+                                    self.aspect_ratio = preset.ratio();
+                                    self.height = ((self.width as f32 / self.aspect_ratio).round() as usize).clamp(1, 4096);
+                                    self.rerender = true;
+                                }
+                            }
+                        });
+                });
                 if ui
                     .add(egui::Slider::new(&mut self.num_points, 1..=5000).text("Points"))
                     .changed()
@@ -353,50 +741,212 @@ impl eframe::App for MyApp {
                 {
                     self.rerender = true;
                 }
+                ui.horizontal(|ui| {
+                    ui.label("Seed");
+                    if ui.add(egui::DragValue::new(&mut self.seed)).changed() {
+                        self.rerender = true;
+                    }
+                    if ui.button("New seed").clicked() {
+                        self.seed = rand::random();
+                        self.rerender = true;
+                    }
+                });
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui
+                    .add(egui::Slider::new(&mut self.threads, 1..=render::default_threads().max(16)).text("Threads"))
+                    .changed()
+                {
+                    self.rerender = true;
+                }
+
+                ui.separator();
+                ui.heading("Appearance");
+                ui.horizontal(|ui| {
+                    ui.label("Background");
+                    let mut this_color: Color32 = Rgba::from_rgb(
+                        self.background_color.r,
+                        self.background_color.g,
+                        self.background_color.b,
+                    )
+                    .into();
+                    let response = ui.add_enabled_ui(!self.transparent_background, |ui| {
+                        ui.color_edit_button_srgba(&mut this_color)
+                    });
+                    if response.inner.changed() {
+                        self.background_color = Color {
+                            r: this_color.r() as f32 / 255.0,
+                            g: this_color.g() as f32 / 255.0,
+                            b: this_color.b() as f32 / 255.0,
+                        };
+                        self.update_histograms();
+                    }
+                    if ui.checkbox(&mut self.transparent_background, "Transparent").changed() {
+                        self.update_histograms();
+                    }
+                });
+                self.preview.ui(ui);
+                if ui
+                    .add(egui::Slider::new(&mut self.filter_settings.oversample, 1..=4).text("Oversampling"))
+                    .changed()
+                {
+                    self.rerender = true;
+                }
+                egui::ComboBox::from_label("Downsampling filter")
+                    .selected_text(self.filter_settings.downsample.get_name())
+                    .show_ui(ui, |ui| {
+                        for filter in DownsampleFilter::iter() {
+                            if ui
+                                .selectable_value(&mut self.filter_settings.downsample, filter, filter.get_name())
+                                .changed()
+                            {
+                                self.rerender = true;
+                            }
+                        }
+                    });
+                if ui
+                    .add(egui::Slider::new(&mut self.filter_settings.estimator_radius, 0.0..=10.0).text("Density blur radius"))
+                    .changed()
+                {
+                    self.rerender = true;
+                }
+                if ui
+                    .add(egui::Slider::new(&mut self.filter_settings.estimator_min_radius, 0.0..=self.filter_settings.estimator_radius).text("Minimum blur radius"))
+                    .changed()
+                {
+                    self.rerender = true;
+                }
+                if ui
+                    .add(egui::Slider::new(&mut self.filter_settings.estimator_curve, 0.0..=1.0).text("Blur falloff"))
+                    .changed()
+                {
+                    self.rerender = true;
+                }
+
+                if self.coloring.ui(ui) {
+                    self.rerender = true;
+                }
+                if self.coloring.mode == ColoringMode::OrbitTrap {
+                    ui.checkbox(&mut self.show_trap, "Show the trap on the canvas");
+                }
+
+                ui.separator();
+                ui.heading("Symmetry");
+                ui.label("Rotated and mirrored copies of every transform are added when rendering; only the originals below are edited.");
+                egui::ComboBox::from_label("Symmetry group")
+                    .selected_text(self.symmetry.kind.get_name())
+                    .show_ui(ui, |ui| {
+                        for kind in SymmetryKind::iter() {
+                            if ui
+                                .selectable_value(&mut self.symmetry.kind, kind, kind.get_name())
+                                .changed()
+                            {
+                                self.rerender = true;
+                            }
+                        }
+                    });
+                if self.symmetry.kind != SymmetryKind::None {
+                    if ui
+                        .add(egui::Slider::new(&mut self.symmetry.order, 2..=12).text("Order"))
+                        .changed()
+                    {
+                        self.rerender = true;
+                    }
+                    if ui
+                        .add(egui::Slider::new(&mut self.symmetry.color_shift, 0.0..=1.0).text("Hue shift per copy"))
+                        .changed()
+                    {
+                        self.rerender = true;
+                    }
+                }
+
+                ui.separator();
+                ui.heading("Filled Julia set");
+                let julia_labels: Vec<(usize, String)> = self.animation_sequence.ifs_vec[0]
+                    .transforms
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, transform)| matches!(transform, Transform::InverseJuliaTransform(_)))
+                    .map(|(index, transform)| {
+                        // Plain barnsley configs carry no transform info until the panel below fills it in.
+                        let info = self.transform_info.get(index).cloned().unwrap_or_default();
+                        (index, info.label(transform, index))
+                    })
+                    .collect();
+                if self.escape_time.ui(ui, &julia_labels) {
+                    self.rerender = true;
+                }
 
                 // Render transform UI
                 ui.separator();
                 ui.heading("Transforms");
+                let flags: Vec<_> = self.transform_info.iter().map(|info| info.flags).collect();
+                if let Some(average) = diagnostics::average_log_contraction(&self.animation_sequence.ifs_vec[0], &self.contractions, &flags) {
+                    if average >= 0.0 {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("On average the transforms expand (mean log contraction {average:.2}), so the chaos game may diverge."),
+                        );
+                    }
+                }
+                let non_finite = self.rendered_image.non_finite;
+                if non_finite > 0 {
+                    let total = self.num_points * self.num_iterations;
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("{non_finite} of {total} points in the last render were infinite or NaN."),
+                    );
+                }
+                if ui.checkbox(&mut self.show_analysis, "Show analysis").changed() && self.show_analysis {
+                    self.rerender = true;
+                }
+                if ui.checkbox(&mut self.show_histograms, "Show histograms").changed() {
+                    self.update_histograms();
+                }
+                ui.checkbox(&mut self.show_overlay, "Show linear and affine transforms on the canvas");
+                ui.collapsing("Parameter ranges", |ui| {
+                    ui.label("Slider ranges for each parameter. Values outside them can still be typed in; hold Shift while dragging a value for finer steps.");
+                    self.parameter_ranges.ui(ui);
+                });
                 self.render_transform_ui(ui, 0);
+                ui.collapsing("Final transform", |ui| self.render_final_transform_ui(ui));
+                ui.collapsing("Transition matrix", |ui| {
+                    let transforms = &self.animation_sequence.ifs_vec[0].transforms;
+                    let labels: Vec<String> = transforms
+                        .iter()
+                        .enumerate()
+                        .map(|(index, transform)| self.transform_info[index].label(transform, index))
+                        .collect();
+                    if self.transitions.ui(ui, &labels) {
+                        self.rerender = true;
+                    }
+                });
 
+                // While something is dragged, render a cheap preview and keep polling until the
+                // drag ends, then catch up at full quality.
+                let dragging = ctx.memory(|memory| memory.is_anything_being_dragged());
                 if self.rerender {
-                    self.rendered_image = self.animation_sequence.animate_single_step(
-                        self.width,
-                        self.height,
-                        self.num_iterations,
-                        self.num_points,
-                        1,
-                    );
+                    self.render(self.preview.enabled && dragging);
                     self.counter += 1;
                     self.rerender = false;
+                } else if self.showing_preview && !dragging {
+                    self.render_image();
+                }
+                if self.showing_preview {
+                    ctx.request_repaint();
                 }
 
-                if self.delete_triggered {
-                    if self.animation_sequence.ifs_vec.get(0).unwrap().len() > 1 {
-                        for ifs in self.animation_sequence.ifs_vec.iter_mut() {
-                            ifs.delete_transform(self.transform_to_delete);
-                        }
-                        self.delete_triggered = false;
-                        self.rendered_image = self.animation_sequence.animate_single_step(
-                            self.width,
-                            self.height,
-                            self.num_iterations,
-                            self.num_points,
-                            1,
-                        );
-                    } else {  // cannot delete since there's only one transform left
-                        self.delete_triggered = false;
-                    } 
+                if let Some((index, action)) = self.pending_action.take() {
+                    self.apply_transform_action(index, action);
                 }
                 ui.separator();
                 ui.heading("Add transform");
                 egui::ComboBox::from_label("")
                     .selected_text(self.selected_transform_to_add.get_name())
                     .show_ui(ui, |ui| {
-                        for t in Transform::iter() {
+                        for t in Transform::kinds() {
                             ui.selectable_value(
                                 &mut self.selected_transform_to_add,
-                                t,
+                                t.clone(),
                                 t.get_name(),
                             );
                         }
@@ -404,32 +954,101 @@ impl eframe::App for MyApp {
 
                 if ui.button("Add").clicked() {
                     for ifs in &mut self.animation_sequence.ifs_vec.iter_mut() {
-                        ifs.add_transform(self.selected_transform_to_add);
+                        ifs.add_transform(self.selected_transform_to_add.clone());
                     }
-                    self.rendered_image = self.animation_sequence.animate_single_step(
-                        self.width,
-                        self.height,
-                        self.num_iterations,
-                        self.num_points,
-                        1,
-                    );
+                    self.render_image();
                 }
 
                 ui.end_row();
             });
 
+        if let Some(analysis) = &self.analysis {
+            let transforms = &self.animation_sequence.ifs_vec[0].transforms;
+            let labels: Vec<String> = transforms
+                .iter()
+                .enumerate()
+                .map(|(index, transform)| self.transform_info[index].label(transform, index))
+                .collect();
+            egui::Window::new("Analysis")
+                .open(&mut self.show_analysis)
+                .show(ctx, |ui| analysis.ui(ui, &labels));
+        }
+
+        if let Some(histograms) = &self.histograms {
+            egui::Window::new("Histograms")
+                .open(&mut self.show_histograms)
+                .show(ctx, |ui| histograms.ui(ui));
+        }
+
+        if self.show_sweep {
+            let ifs = &self.animation_sequence.ifs_vec[0];
+            let labels: Vec<String> = ifs
+                .transforms
+                .iter()
+                .enumerate()
+                .map(|(index, transform)| self.transform_info[index].label(transform, index))
+                .collect();
+            let options = RenderOptions {
+                flags: self.transform_info.iter().map(|info| info.flags).collect(),
+                symmetry: self.symmetry,
+                transitions: self.transitions.clone(),
+                coloring: self.coloring.clone(),
+                threads: self.threads,
+                seed: self.seed,
+            };
+            let evaluation = (self.num_iterations, self.num_points);
+            let background = self.background_color;
+            let sweep_tool = &mut self.sweep_tool;
+            egui::Window::new("Parameter sweep")
+                .open(&mut self.show_sweep)
+                .show(ctx, |ui| sweep_tool.ui(ui, ifs, &labels, &options, evaluation, background));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut open = self.show_fitting;
+            let accepted = egui::Window::new("Fit to image")
+                .open(&mut open)
+                .show(ctx, |ui| self.fitting.ui(ui))
+                .and_then(|response| response.inner.flatten());
+            if self.show_fitting && !open {
+                self.fitting.stop();
+            }
+            self.show_fitting = open;
+            if let Some(maps) = accepted {
+                self.apply_fit(&maps);
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             let mut bytes: Vec<u8> = Vec::new();
-            let save_scale =
-                1.max((self.num_points * self.num_iterations) / (self.width * self.height));
-            let buffer = array_to_image(self.rendered_image.to_u8(save_scale));
+            let buffer = array_to_image(self.rendered_image.to_u8(self.rendered_scale, self.background()));
             let _ = buffer.write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Png);
             ui.ctx().forget_image("bytes://ifs.png"); // since uris are cached, we have to clear it
-            ui.add(
-                egui::Image::from_bytes("bytes://ifs.png", bytes)
-                    .max_size(Vec2::new(10000.0, 10000.0))
-                    .fit_to_exact_size(Vec2::new(self.height as f32, self.width as f32)),
-            );
+            let image_size = Vec2::new(self.width as f32, self.height as f32);
+            let available = ui.available_size();
+            let fit = (available.x / image_size.x).min(available.y / image_size.y);
+            let (rect, _) = ui.allocate_exact_size(image_size * fit, Sense::hover());
+            if self.transparent_background {
+                paint_checkerboard(ui, rect);
+            }
+            egui::Image::from_bytes("bytes://ifs.png", bytes).paint_at(ui, rect);
+
+            if self.show_overlay {
+                let mapping = CanvasMapping::new(rect, self.width, self.height);
+                let transforms = &mut self.animation_sequence.ifs_vec[0].transforms;
+                if transform_overlay(ui, mapping, transforms) {
+                    self.rerender = true;
+                    ui.ctx().request_repaint();
+                }
+            }
+            if self.show_trap && self.coloring.mode == ColoringMode::OrbitTrap {
+                let mapping = CanvasMapping::new(rect, self.width, self.height);
+                if trap_overlay(ui, mapping, &mut self.coloring.trap) {
+                    self.rerender = true;
+                    ui.ctx().request_repaint();
+                }
+            }
         });
     }
 }
@@ -0,0 +1,33 @@
+use strum::EnumIter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum AspectPreset {
+    Square,
+    Standard,
+    Widescreen,
+    Paper,
+    PhoneWallpaper,
+}
+
+impl AspectPreset {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            AspectPreset::Square => "1:1",
+            AspectPreset::Standard => "4:3",
+            AspectPreset::Widescreen => "16:9",
+            AspectPreset::Paper => "A-series paper (1:√2)",
+            AspectPreset::PhoneWallpaper => "Phone wallpaper (9:19.5)",
+        }
+    }
+
+    /// Width divided by height.
+    pub fn ratio(&self) -> f32 {
+        match self {
+            AspectPreset::Square => 1.0,
+            AspectPreset::Standard => 4.0 / 3.0,
+            AspectPreset::Widescreen => 16.0 / 9.0,
+            AspectPreset::Paper => 1.0 / std::f32::consts::SQRT_2,
+            AspectPreset::PhoneWallpaper => 9.0 / 19.5,
+        }
+    }
+}
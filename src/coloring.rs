@@ -0,0 +1,330 @@
+use barnsley::util::{Color, Point};
+use egui::{Color32, Rgba, Sense, Ui, Vec2};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator};
+
+/// What decides the color a point is plotted with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum ColoringMode {
+    /// Each step mixes in the applied transform's base color.
+    #[default]
+    BaseColor,
+    /// The transform applied last, spread evenly over the gradient.
+    LastTransform,
+    /// How many steps the walker has taken, so points still converging onto the
+    /// attractor stand out.
+    Depth,
+    /// How close the walker's recent points came to the orbit trap.
+    OrbitTrap,
+    /// How far the last step moved the point.
+    Velocity,
+}
+
+impl ColoringMode {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ColoringMode::BaseColor => "Base colors",
+            ColoringMode::LastTransform => "Last transform",
+            ColoringMode::Depth => "Iteration depth",
+            ColoringMode::OrbitTrap => "Orbit trap",
+            ColoringMode::Velocity => "Velocity",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum TrapShape {
+    #[default]
+    Point,
+    Line,
+    Circle,
+}
+
+impl TrapShape {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            TrapShape::Point => "Point",
+            TrapShape::Line => "Line",
+            TrapShape::Circle => "Circle",
+        }
+    }
+}
+
+/// A shape in canvas coordinates that points are colored by their distance to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OrbitTrap {
+    pub shape: TrapShape,
+    pub x: f32,
+    pub y: f32,
+    /// Direction of the line, in radians from the x axis.
+    pub angle: f32,
+    /// Radius of the circle.
+    pub radius: f32,
+}
+
+impl Default for OrbitTrap {
+    fn default() -> Self {
+        Self {
+            shape: TrapShape::Point,
+            x: 0.0,
+            y: 0.0,
+            angle: 0.0,
+            radius: 0.5,
+        }
+    }
+}
+
+impl OrbitTrap {
+    pub fn distance(&self, point: Point) -> f32 {
+        let (dx, dy) = (point.x - self.x, point.y - self.y);
+        match self.shape {
+            TrapShape::Point => dx.hypot(dy),
+            TrapShape::Line => {
+                let (sin, cos) = self.angle.sin_cos();
+                (dy * cos - dx * sin).abs()
+            }
+            TrapShape::Circle => (dx.hypot(dy) - self.radius).abs(),
+        }
+    }
+}
+
+/// Colors evenly spaced over `[0, 1]`, blended linearly in between.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+    pub stops: Vec<Color>,
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self::presets().remove(0).1
+    }
+}
+
+impl Gradient {
+    pub fn presets() -> Vec<(&'static str, Gradient)> {
+        let gradient = |stops: &[[f32; 3]]| Gradient {
+            stops: stops.iter().map(|&[r, g, b]| Color { r, g, b }).collect(),
+        };
+        vec![
+            ("Fire", gradient(&[[0.1, 0.0, 0.0], [0.8, 0.1, 0.0], [1.0, 0.6, 0.0], [1.0, 1.0, 0.7]])),
+            ("Ocean", gradient(&[[0.0, 0.05, 0.2], [0.0, 0.35, 0.6], [0.2, 0.8, 0.8], [0.9, 1.0, 1.0]])),
+            ("Viridis", gradient(&[[0.27, 0.0, 0.33], [0.23, 0.32, 0.55], [0.13, 0.57, 0.55], [0.37, 0.79, 0.38], [0.99, 0.91, 0.14]])),
+            ("Rainbow", gradient(&[[1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0], [0.0, 1.0, 1.0], [0.0, 0.0, 1.0], [1.0, 0.0, 1.0]])),
+            ("Grayscale", gradient(&[[0.1, 0.1, 0.1], [1.0, 1.0, 1.0]])),
+        ]
+    }
+
+    /// The color at `t`, clamped to `[0, 1]`.
+    pub fn sample(&self, t: f32) -> Color {
+        match self.stops.len() {
+            0 => Color { r: 1.0, g: 1.0, b: 1.0 },
+            1 => self.stops[0],
+            len => {
+                let position = t.clamp(0.0, 1.0) * (len - 1) as f32;
+                let index = (position.floor() as usize).min(len - 2);
+                let (a, b) = (self.stops[index], self.stops[index + 1]);
+                let f = position - index as f32;
+                Color {
+                    r: a.r + (b.r - a.r) * f,
+                    g: a.g + (b.g - a.g) * f,
+                    b: a.b + (b.b - a.b) * f,
+                }
+            }
+        }
+    }
+
+    /// A strip previewing the gradient followed by an editable swatch per stop. Returns
+    /// whether anything changed.
+    pub fn ui(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        let (rect, _) = ui.allocate_exact_size(Vec2::new(ui.available_width().min(240.0), 12.0), Sense::hover());
+        let steps = rect.width().max(1.0) as usize;
+        for step in 0..steps {
+            let color = self.sample(step as f32 / (steps - 1).max(1) as f32);
+            let x = rect.left() + step as f32;
+            ui.painter().vline(x, rect.y_range(), egui::Stroke::new(1.0, to_color32(color)));
+        }
+        ui.horizontal(|ui| {
+            let mut remove = None;
+            for (index, stop) in self.stops.iter_mut().enumerate() {
+                let mut color = to_color32(*stop);
+                let response = ui.color_edit_button_srgba(&mut color);
+                if response.changed() {
+                    *stop = Color {
+                        r: color.r() as f32 / 255.0,
+                        g: color.g() as f32 / 255.0,
+                        b: color.b() as f32 / 255.0,
+                    };
+                    changed = true;
+                }
+                if response.secondary_clicked() {
+                    remove = Some(index);
+                }
+            }
+            if let Some(index) = remove.filter(|_| self.stops.len() > 2) {
+                self.stops.remove(index);
+                changed = true;
+            }
+            if ui.small_button("+").on_hover_text("Add a stop; right-click a stop to remove it").clicked() {
+                // A loaded gradient can have no stops at all; start it from the color `sample` falls back to.
+                let last = self.stops.last().copied().unwrap_or(Color { r: 1.0, g: 1.0, b: 1.0 });
+                self.stops.push(last);
+                changed = true;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Presets");
+            for (name, gradient) in Self::presets() {
+                if ui.small_button(name).clicked() {
+                    *self = gradient;
+                    changed = true;
+                }
+            }
+        });
+        changed
+    }
+}
+
+fn to_color32(color: Color) -> Color32 {
+    Rgba::from_rgb(color.r, color.g, color.b).into()
+}
+
+/// How points are colored, saved with the document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Coloring {
+    pub mode: ColoringMode,
+    #[serde(default)]
+    pub gradient: Gradient,
+    #[serde(default)]
+    pub trap: OrbitTrap,
+    /// Steps it takes a walker to reach the end of the gradient in depth mode.
+    #[serde(default = "default_depth_range")]
+    pub depth_range: usize,
+    /// Trap distance or step length that reaches the end of the gradient.
+    #[serde(default = "default_distance_scale")]
+    pub distance_scale: f32,
+}
+
+fn default_depth_range() -> usize {
+    10
+}
+
+fn default_distance_scale() -> f32 {
+    0.5
+}
+
+impl Default for Coloring {
+    fn default() -> Self {
+        Self {
+            mode: ColoringMode::BaseColor,
+            gradient: Gradient::default(),
+            trap: OrbitTrap::default(),
+            depth_range: default_depth_range(),
+            distance_scale: default_distance_scale(),
+        }
+    }
+}
+
+/// What one walker remembers between steps for coloring.
+pub struct Orbit {
+    color: Color,
+    point: Point,
+    depth: usize,
+    trap: f32,
+}
+
+impl Coloring {
+    /// Starts a walker at `point` whose first transform has base color `color`.
+    pub fn start(&self, point: Point, color: Color) -> Orbit {
+        Orbit {
+            color,
+            point,
+            depth: 0,
+            trap: self.trap.distance(point),
+        }
+    }
+
+    /// The color of the point a walker just moved to. `transform` is the position of the
+    /// applied transform in the list, scaled to `[0, 1]`, and `base` its base color.
+    pub fn step(&self, orbit: &mut Orbit, point: Point, transform: f32, base: Color) -> Color {
+        let previous = std::mem::replace(&mut orbit.point, point);
+        orbit.depth += 1;
+        orbit.trap = (orbit.trap + self.trap.distance(point)) / 2.0;
+        let scale = self.distance_scale.max(f32::EPSILON);
+        let t = match self.mode {
+            ColoringMode::BaseColor => {
+                orbit.color = Color {
+                    r: (orbit.color.r + base.r) / 2.0,
+                    g: (orbit.color.g + base.g) / 2.0,
+                    b: (orbit.color.b + base.b) / 2.0,
+                };
+                return orbit.color;
+            }
+            ColoringMode::LastTransform => transform,
+            ColoringMode::Depth => orbit.depth as f32 / self.depth_range.max(1) as f32,
+            ColoringMode::OrbitTrap => orbit.trap / scale,
+            ColoringMode::Velocity => (point.x - previous.x).hypot(point.y - previous.y) / scale,
+        };
+        self.gradient.sample(t)
+    }
+
+    /// Returns whether anything changed.
+    pub fn ui(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        egui::ComboBox::from_label("Coloring")
+            .selected_text(self.mode.get_name())
+            .show_ui(ui, |ui| {
+                for mode in ColoringMode::iter() {
+                    changed |= ui.selectable_value(&mut self.mode, mode, mode.get_name()).changed();
+                }
+            });
+        match self.mode {
+            ColoringMode::BaseColor => return changed,
+            ColoringMode::LastTransform => {}
+            ColoringMode::Depth => {
+                changed |= ui
+                    .add(egui::Slider::new(&mut self.depth_range, 1..=100).text("Steps across gradient"))
+                    .changed();
+            }
+            ColoringMode::OrbitTrap => {
+                egui::ComboBox::from_label("Trap shape")
+                    .selected_text(self.trap.shape.get_name())
+                    .show_ui(ui, |ui| {
+                        for shape in TrapShape::iter() {
+                            changed |= ui
+                                .selectable_value(&mut self.trap.shape, shape, shape.get_name())
+                                .changed();
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.label("Center");
+                    changed |= ui.add(egui::DragValue::new(&mut self.trap.x).speed(0.01)).changed();
+                    changed |= ui.add(egui::DragValue::new(&mut self.trap.y).speed(0.01)).changed();
+                });
+                match self.trap.shape {
+                    TrapShape::Point => {}
+                    TrapShape::Line => {
+                        changed |= ui
+                            .add(egui::Slider::new(&mut self.trap.angle, 0.0..=std::f32::consts::PI).text("Angle"))
+                            .changed();
+                    }
+                    TrapShape::Circle => {
+                        changed |= ui
+                            .add(egui::Slider::new(&mut self.trap.radius, 0.0..=2.0).text("Radius"))
+                            .changed();
+                    }
+                }
+                changed |= ui
+                    .add(egui::Slider::new(&mut self.distance_scale, 0.01..=2.0).logarithmic(true).text("Distance across gradient"))
+                    .changed();
+            }
+            ColoringMode::Velocity => {
+                changed |= ui
+                    .add(egui::Slider::new(&mut self.distance_scale, 0.01..=2.0).logarithmic(true).text("Step length across gradient"))
+                    .changed();
+            }
+        }
+        changed |= self.gradient.ui(ui);
+        changed
+    }
+}
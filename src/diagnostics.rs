@@ -0,0 +1,128 @@
+use barnsley::util::Point;
+use egui::Color32;
+use num_complex::Complex;
+
+use crate::ifs::IFS;
+use crate::render::TransformFlags;
+use crate::transform::{weight, Transform};
+
+/// Points per side of the grid nonlinear transforms are sampled on, covering `[-1, 1]^2`.
+const SAMPLES: usize = 9;
+/// Step for the finite differences.
+const STEP: f32 = 1e-3;
+
+/// How far a transform can stretch distances: its Lipschitz constant, exactly for
+/// linear and affine transforms and estimated from [`jacobian`] on a grid of points otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contraction {
+    pub factor: f32,
+    pub exact: bool,
+}
+
+impl Contraction {
+    pub fn of(transform: &Transform) -> Self {
+        match transform {
+            Transform::LinearTransform(t) => Self::exact(spectral_norm(t.a, t.b, t.c, t.d)),
+            Transform::AffineTransform(t) => Self::exact(spectral_norm(t.a, t.b, t.c, t.d)),
+            _ => Self::sampled(|x, y| {
+                let [a, b, c, d] = jacobian(transform, Point { x, y });
+                spectral_norm(a, b, c, d)
+            }),
+        }
+    }
+
+    fn exact(factor: f32) -> Self {
+        Self { factor, exact: true }
+    }
+
+    /// The largest finite value of `stretch` over the sample grid.
+    fn sampled(stretch: impl Fn(f32, f32) -> f32) -> Self {
+        let mut factor: f32 = 0.0;
+        for row in 0..SAMPLES {
+            for col in 0..SAMPLES {
+                let x = -1.0 + 2.0 * col as f32 / (SAMPLES - 1) as f32;
+                let y = -1.0 + 2.0 * row as f32 / (SAMPLES - 1) as f32;
+                let value = stretch(x, y);
+                if value.is_finite() {
+                    factor = factor.max(value);
+                }
+            }
+        }
+        Self { factor, exact: false }
+    }
+
+    /// Short text for the badge, marking estimates with `≈`.
+    pub fn label(&self) -> String {
+        let prefix = if self.exact { "" } else { "≈" };
+        format!("{prefix}{:.2}", self.factor)
+    }
+
+    /// Green when the transform contracts, amber when only barely, red when it expands.
+    pub fn color(&self) -> Color32 {
+        if self.factor < 0.95 {
+            Color32::from_rgb(80, 180, 80)
+        } else if self.factor < 1.0 {
+            Color32::from_rgb(220, 170, 40)
+        } else {
+            Color32::from_rgb(220, 70, 60)
+        }
+    }
+}
+
+/// The derivative of `transform` at `point` as `[a, b, c, d]` with `dx' = a dx + b dy` and
+/// `dy' = c dx + d dy`. Moebius and inverse Julia transforms are conformal and done exactly,
+/// which also sidesteps the random choice of square root; the rest use finite differences.
+pub fn jacobian(transform: &Transform, point: Point) -> [f32; 4] {
+    let conformal = |derivative: Complex<f32>| [derivative.re, -derivative.im, derivative.im, derivative.re];
+    let z = Complex::new(point.x, point.y);
+    match transform {
+        Transform::LinearTransform(t) => [t.a, t.b, t.c, t.d],
+        Transform::AffineTransform(t) => [t.a, t.b, t.c, t.d],
+        Transform::MoebiusTransform(t) => {
+            let denominator = t.c * z + t.d;
+            conformal((t.a * t.d - t.b * t.c) / (denominator * denominator))
+        }
+        Transform::InverseJuliaTransform(t) => {
+            let c = Complex::from_polar(t.r, t.theta);
+            conformal(0.5 / (z - c).sqrt())
+        }
+        _ => {
+            let at = |x: f32, y: f32| transform.transform_point(Point { x, y });
+            let (right, left) = (at(point.x + STEP, point.y), at(point.x - STEP, point.y));
+            let (up, down) = (at(point.x, point.y + STEP), at(point.x, point.y - STEP));
+            [
+                (right.x - left.x) / (2.0 * STEP),
+                (up.x - down.x) / (2.0 * STEP),
+                (right.y - left.y) / (2.0 * STEP),
+                (up.y - down.y) / (2.0 * STEP),
+            ]
+        }
+    }
+}
+
+/// The largest singular value of `[[a, b], [c, d]]`.
+pub fn spectral_norm(a: f32, b: f32, c: f32, d: f32) -> f32 {
+    let half_sum = (a * a + b * b + c * c + d * d) / 2.0;
+    let determinant = a * d - b * c;
+    (half_sum + (half_sum * half_sum - determinant * determinant).max(0.0).sqrt()).sqrt()
+}
+
+/// The chaos game's expected log contraction per step, `sum p_i ln(L_i)` over the transforms
+/// that are not muted. The attractor is only guaranteed to exist when this is negative.
+/// A transition matrix changes the probabilities and is not taken into account.
+pub fn average_log_contraction(ifs: &IFS, contractions: &[Contraction], flags: &[TransformFlags]) -> Option<f32> {
+    let mut total_weight = 0.0;
+    let mut sum = 0.0;
+    for (index, (transform, contraction)) in ifs.transforms.iter().zip(contractions).enumerate() {
+        if flags.get(index).copied().unwrap_or_default().muted {
+            continue;
+        }
+        let weight = weight(transform).max(0.0);
+        if weight == 0.0 {
+            continue;
+        }
+        total_weight += weight;
+        sum += weight * contraction.factor.ln();
+    }
+    (total_weight > 0.0).then(|| sum / total_weight)
+}
@@ -0,0 +1,174 @@
+use std::io::Cursor;
+
+use barnsley::util::Color;
+use image::codecs::hdr::HdrEncoder;
+use image::{
+    DynamicImage, ImageBuffer, ImageOutputFormat, ImageResult, Rgb, Rgb32FImage, Rgba,
+    Rgba32FImage,
+};
+use strum::EnumIter;
+
+use crate::render::Accumulator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum ExportFormat {
+    Png8,
+    Png16,
+    OpenExr,
+    Hdr,
+}
+
+impl ExportFormat {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Png8 => "PNG (8-bit)",
+            ExportFormat::Png16 => "PNG (16-bit)",
+            ExportFormat::OpenExr => "OpenEXR (32-bit float)",
+            ExportFormat::Hdr => "Radiance HDR",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png8 | ExportFormat::Png16 => "png",
+            ExportFormat::OpenExr => "exr",
+            ExportFormat::Hdr => "hdr",
+        }
+    }
+
+    /// Radiance HDR has no alpha channel, so the alpha option is ignored for it.
+    pub fn supports_alpha(&self) -> bool {
+        !matches!(self, ExportFormat::Hdr)
+    }
+}
+
+/// Encodes the accumulated radiance divided by `scale`.
+///
+/// Float formats keep the linear values unclipped with alpha premultiplied,
+/// as compositing tools expect, and never have `background` applied. PNG is
+/// laid over `background` unless it has an alpha channel, in which case it
+/// stores straight alpha like the on-screen preview.
+pub fn encode(
+    accumulator: &Accumulator,
+    scale: usize,
+    format: ExportFormat,
+    include_alpha: bool,
+    background: Color,
+) -> ImageResult<Vec<u8>> {
+    let (width, height) = (accumulator.width as u32, accumulator.height as u32);
+    let linear = accumulator.linear(scale);
+    let alpha = accumulator.alpha(scale);
+    let include_alpha = include_alpha && format.supports_alpha();
+
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut cursor = Cursor::new(&mut bytes);
+    match format {
+        ExportFormat::Png8 | ExportFormat::Png16 => {
+            let background = if include_alpha { None } else { Some(background) };
+            let composite = accumulator.composite(scale, background);
+            // `composite` is laid out row by row, as image buffers are.
+            let samples = composite
+                .indexed_iter()
+                .filter(|((_, _, channel), _)| include_alpha || *channel < 3)
+                .map(|(_, &value)| value);
+            let image = if format == ExportFormat::Png8 {
+                let raw: Vec<u8> = samples.map(|value| (value * u8::MAX as f32).round() as u8).collect();
+                if include_alpha {
+                    DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, raw).unwrap())
+                } else {
+                    DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, raw).unwrap())
+                }
+            } else {
+                let raw: Vec<u16> = samples.map(|value| (value * u16::MAX as f32).round() as u16).collect();
+                if include_alpha {
+                    DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, raw).unwrap())
+                } else {
+                    DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, raw).unwrap())
+                }
+            };
+            image.write_to(&mut cursor, ImageOutputFormat::Png)?;
+        }
+        ExportFormat::OpenExr => {
+            let image = if include_alpha {
+                DynamicImage::ImageRgba32F(Rgba32FImage::from_fn(width, height, |x, y| {
+                    let (row, col) = (y as usize, x as usize);
+                    Rgba([
+                        linear[[row, col, 0]],
+                        linear[[row, col, 1]],
+                        linear[[row, col, 2]],
+                        alpha[[row, col]],
+                    ])
+                }))
+            } else {
+                DynamicImage::ImageRgb32F(Rgb32FImage::from_fn(width, height, |x, y| {
+                    let (row, col) = (y as usize, x as usize);
+                    Rgb([
+                        linear[[row, col, 0]],
+                        linear[[row, col, 1]],
+                        linear[[row, col, 2]],
+                    ])
+                }))
+            };
+            image.write_to(&mut cursor, ImageOutputFormat::OpenExr)?;
+        }
+        ExportFormat::Hdr => {
+            let pixels: Vec<Rgb<f32>> = linear
+                .outer_iter()
+                .flat_map(|row| {
+                    row.outer_iter()
+                        .map(|pixel| Rgb([pixel[0], pixel[1], pixel[2]]))
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            HdrEncoder::new(&mut cursor).encode(&pixels, width as usize, height as usize)?;
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two pixels with `scale` 2: one hit twice by orange points and fully opaque, one empty.
+    fn accumulator() -> Accumulator {
+        let mut accumulator = Accumulator::new(2, 1);
+        accumulator.hits[[0, 0]] = 2.0;
+        for (channel, value) in [2.0, 1.0, 0.0].into_iter().enumerate() {
+            accumulator.radiance[[0, 0, channel]] = value;
+        }
+        accumulator
+    }
+
+    const BLUE: Color = Color { r: 0.0, g: 0.0, b: 1.0 };
+
+    #[test]
+    fn png8_keeps_colors_over_the_background() {
+        let bytes = encode(&accumulator(), 2, ExportFormat::Png8, false, BLUE).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(image.color(), image::ColorType::Rgb8);
+        let image = image.to_rgb8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 128, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255]);
+    }
+
+    #[test]
+    fn png8_with_alpha_leaves_empty_pixels_transparent() {
+        let bytes = encode(&accumulator(), 2, ExportFormat::Png8, true, BLUE).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(image.color(), image::ColorType::Rgba8);
+        let image = image.to_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 128, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn png16_uses_the_full_range() {
+        let bytes = encode(&accumulator(), 2, ExportFormat::Png16, false, BLUE).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(image.color(), image::ColorType::Rgb16);
+        let image = image.to_rgb16();
+        assert_eq!(image.get_pixel(0, 0).0, [65535, 32768, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 65535]);
+    }
+}
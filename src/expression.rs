@@ -0,0 +1,581 @@
+use std::f32::consts::{E, PI};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+/// Whether formulas work on the real coordinates `x` and `y` or on `z = x + iy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum ExpressionMode {
+    #[default]
+    Real,
+    Complex,
+}
+
+impl ExpressionMode {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ExpressionMode::Real => "x', y'",
+            ExpressionMode::Complex => "z'",
+        }
+    }
+
+    /// The names formulas may use for the input point, in the order they are passed in.
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            ExpressionMode::Real => &["x", "y", "r", "theta"],
+            ExpressionMode::Complex => &["z"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Sqrt,
+    Abs,
+    // Real only.
+    Atan2,
+    Floor,
+    // Complex only.
+    Re,
+    Im,
+    Conj,
+    Arg,
+}
+
+impl Function {
+    fn lookup(name: &str, mode: ExpressionMode) -> Option<Function> {
+        let function = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "ln" | "log" => Function::Ln,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "atan2" if mode == ExpressionMode::Real => Function::Atan2,
+            "floor" if mode == ExpressionMode::Real => Function::Floor,
+            "re" if mode == ExpressionMode::Complex => Function::Re,
+            "im" if mode == ExpressionMode::Complex => Function::Im,
+            "conj" if mode == ExpressionMode::Complex => Function::Conj,
+            "arg" if mode == ExpressionMode::Complex => Function::Arg,
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Function::Atan2 => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// One instruction of a compiled formula, run on a stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Constant(f32),
+    ImaginaryUnit,
+    Variable(usize),
+    Parameter(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Neg,
+    Call(Function),
+}
+
+/// The arithmetic a formula needs, for `f32` in real mode and `Complex<f32>` in complex mode.
+pub trait Number:
+    Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+{
+    fn from_real(value: f32) -> Self;
+    fn imaginary_unit() -> Self;
+    fn pow(self, exponent: Self) -> Self;
+    fn call(function: Function, arguments: &[Self]) -> Self;
+}
+
+impl Number for f32 {
+    fn from_real(value: f32) -> Self {
+        value
+    }
+
+    /// Never compiled into a real formula.
+    fn imaginary_unit() -> Self {
+        f32::NAN
+    }
+
+    fn pow(self, exponent: Self) -> Self {
+        self.powf(exponent)
+    }
+
+    fn call(function: Function, arguments: &[Self]) -> Self {
+        let a = arguments[0];
+        match function {
+            Function::Sin => a.sin(),
+            Function::Cos => a.cos(),
+            Function::Tan => a.tan(),
+            Function::Sinh => a.sinh(),
+            Function::Cosh => a.cosh(),
+            Function::Tanh => a.tanh(),
+            Function::Exp => a.exp(),
+            Function::Ln => a.ln(),
+            Function::Sqrt => a.sqrt(),
+            Function::Abs => a.abs(),
+            Function::Atan2 => a.atan2(arguments[1]),
+            Function::Floor => a.floor(),
+            Function::Re | Function::Conj => a,
+            Function::Im => 0.0,
+            Function::Arg => 0.0_f32.atan2(a),
+        }
+    }
+}
+
+impl Number for Complex<f32> {
+    fn from_real(value: f32) -> Self {
+        Complex::new(value, 0.0)
+    }
+
+    fn imaginary_unit() -> Self {
+        Complex::i()
+    }
+
+    fn pow(self, exponent: Self) -> Self {
+        // Whole powers are by far the most common and are exact when multiplied out.
+        if exponent.im == 0.0 && exponent.re.fract() == 0.0 && exponent.re.abs() <= 16.0 {
+            self.powi(exponent.re as i32)
+        } else {
+            self.powc(exponent)
+        }
+    }
+
+    fn call(function: Function, arguments: &[Self]) -> Self {
+        let a = arguments[0];
+        match function {
+            Function::Sin => a.sin(),
+            Function::Cos => a.cos(),
+            Function::Tan => a.tan(),
+            Function::Sinh => a.sinh(),
+            Function::Cosh => a.cosh(),
+            Function::Tanh => a.tanh(),
+            Function::Exp => a.exp(),
+            Function::Ln => a.ln(),
+            Function::Sqrt => a.sqrt(),
+            Function::Abs => Complex::new(a.norm(), 0.0),
+            Function::Atan2 => Complex::new(a.re.atan2(arguments[1].re), 0.0),
+            Function::Floor => Complex::new(a.re.floor(), a.im.floor()),
+            Function::Re => Complex::new(a.re, 0.0),
+            Function::Im => Complex::new(a.im, 0.0),
+            Function::Conj => a.conj(),
+            Function::Arg => Complex::new(a.arg(), 0.0),
+        }
+    }
+}
+
+/// A parsed formula, ready to be evaluated many times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    ops: Vec<Op>,
+    stack_size: usize,
+}
+
+impl Program {
+    /// `variables` are indexed like [`ExpressionMode::variables`] and `parameters` like
+    /// the names returned alongside the program by [`compile`].
+    pub fn evaluate<T: Number>(&self, variables: &[T], parameters: &[f32]) -> T {
+        let mut stack: Vec<T> = Vec::with_capacity(self.stack_size);
+        for op in &self.ops {
+            let value = match *op {
+                Op::Constant(value) => T::from_real(value),
+                Op::ImaginaryUnit => T::imaginary_unit(),
+                Op::Variable(index) => variables[index],
+                Op::Parameter(index) => T::from_real(parameters[index]),
+                Op::Neg => -stack.pop().unwrap(),
+                Op::Call(function) => {
+                    let start = stack.len() - function.arity();
+                    let value = T::call(function, &stack[start..]);
+                    stack.truncate(start);
+                    value
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    match op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        _ => a.pow(b),
+                    }
+                }
+            };
+            stack.push(value);
+        }
+        stack.pop().unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Identifier(String),
+    Symbol(char),
+}
+
+/// Splits `source` into tokens, each paired with the character offset it starts at.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < chars.len() {
+        let c = chars[position];
+        let start = position;
+        if c.is_whitespace() {
+            position += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            while position < chars.len() && (chars[position].is_ascii_digit() || chars[position] == '.') {
+                position += 1;
+            }
+            let text: String = chars[start..position].iter().collect();
+            let value = text
+                .parse()
+                .map_err(|_| format!("'{text}' at column {} is not a number", start + 1))?;
+            tokens.push((start, Token::Number(value)));
+        } else if c.is_alphabetic() || c == '_' {
+            while position < chars.len() && (chars[position].is_alphanumeric() || chars[position] == '_') {
+                position += 1;
+            }
+            tokens.push((start, Token::Identifier(chars[start..position].iter().collect())));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            position += 1;
+        } else {
+            return Err(format!("unexpected '{c}' at column {}", start + 1));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, emitting postfix ops as it goes.
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    mode: ExpressionMode,
+    parameters: &'a mut Vec<String>,
+    ops: Vec<Op>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.position).map_or(0, |(column, _)| *column) + 1
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else if self.peek().is_none() {
+            Err(format!("expected '{symbol}' at the end"))
+        } else {
+            Err(format!("expected '{symbol}' at column {}", self.column()))
+        }
+    }
+
+    /// `sum := product (('+' | '-') product)*`
+    fn sum(&mut self) -> Result<(), String> {
+        self.product()?;
+        loop {
+            if self.eat('+') {
+                self.product()?;
+                self.ops.push(Op::Add);
+            } else if self.eat('-') {
+                self.product()?;
+                self.ops.push(Op::Sub);
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// `product := unary (('*' | '/') unary)*`
+    fn product(&mut self) -> Result<(), String> {
+        self.unary()?;
+        loop {
+            if self.eat('*') {
+                self.unary()?;
+                self.ops.push(Op::Mul);
+            } else if self.eat('/') {
+                self.unary()?;
+                self.ops.push(Op::Div);
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// `unary := '-' unary | power`
+    fn unary(&mut self) -> Result<(), String> {
+        if self.eat('-') {
+            self.unary()?;
+            self.ops.push(Op::Neg);
+            Ok(())
+        } else {
+            self.power()
+        }
+    }
+
+    /// `power := atom ('^' unary)?`, so `a^b^c` is `a^(b^c)` and `-a^2` is `-(a^2)`.
+    fn power(&mut self) -> Result<(), String> {
+        self.atom()?;
+        if self.eat('^') {
+            self.unary()?;
+            self.ops.push(Op::Pow);
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<(), String> {
+        let column = self.column();
+        let Some((_, token)) = self.tokens.get(self.position).cloned() else {
+            return Err("the formula ends too early".to_owned());
+        };
+        self.position += 1;
+        match token {
+            Token::Number(value) => self.ops.push(Op::Constant(value)),
+            Token::Symbol('(') => {
+                self.sum()?;
+                self.expect(')')?;
+            }
+            Token::Symbol(symbol) => return Err(format!("unexpected '{symbol}' at column {column}")),
+            Token::Identifier(name) if self.eat('(') => {
+                let function = Function::lookup(&name, self.mode)
+                    .ok_or_else(|| format!("unknown function '{name}' at column {column}"))?;
+                for argument in 0..function.arity() {
+                    if argument > 0 {
+                        self.expect(',')?;
+                    }
+                    self.sum()?;
+                }
+                self.expect(')')?;
+                self.ops.push(Op::Call(function));
+            }
+            Token::Identifier(name) => {
+                let op = if let Some(index) = self.mode.variables().iter().position(|&v| v == name) {
+                    Op::Variable(index)
+                } else if name == "pi" {
+                    Op::Constant(PI)
+                } else if name == "e" {
+                    Op::Constant(E)
+                } else if name == "i" && self.mode == ExpressionMode::Complex {
+                    Op::ImaginaryUnit
+                } else if Function::lookup(&name, self.mode).is_some() {
+                    return Err(format!("'{name}' at column {column} needs parentheses"));
+                } else {
+                    // Anything else is a parameter, shared by every formula of the transform.
+                    let index = match self.parameters.iter().position(|p| *p == name) {
+                        Some(index) => index,
+                        None => {
+                            self.parameters.push(name);
+                            self.parameters.len() - 1
+                        }
+                    };
+                    Op::Parameter(index)
+                };
+                self.ops.push(op);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compiles `source`, adding the names of any parameters it uses that are not yet in
+/// `parameters`. Errors are written for showing next to the formula.
+pub fn compile(source: &str, mode: ExpressionMode, parameters: &mut Vec<String>) -> Result<Program, String> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        mode,
+        parameters,
+        ops: Vec::new(),
+    };
+    if parser.tokens.is_empty() {
+        return Err("the formula is empty".to_owned());
+    }
+    parser.sum()?;
+    if parser.position < parser.tokens.len() {
+        return Err(format!("unexpected input at column {}", parser.column()));
+    }
+
+    let mut depth: usize = 0;
+    let mut stack_size = 0;
+    for op in &parser.ops {
+        depth = match op {
+            Op::Constant(_) | Op::ImaginaryUnit | Op::Variable(_) | Op::Parameter(_) => depth + 1,
+            Op::Neg => depth,
+            Op::Call(function) => depth + 1 - function.arity(),
+            _ => depth - 1,
+        };
+        stack_size = stack_size.max(depth);
+    }
+    Ok(Program {
+        ops: parser.ops,
+        stack_size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates a real-mode formula at `(x, y)`, with parameters given in the order they appear.
+    fn real(source: &str, x: f32, y: f32, parameters: &[f32]) -> f32 {
+        let mut names = Vec::new();
+        let program = compile(source, ExpressionMode::Real, &mut names).unwrap();
+        assert_eq!(names.len(), parameters.len(), "parameters of `{source}`: {names:?}");
+        let variables = [x, y, x.hypot(y), y.atan2(x)];
+        program.evaluate(&variables, parameters)
+    }
+
+    fn complex(source: &str, z: Complex<f32>) -> Complex<f32> {
+        let program = compile(source, ExpressionMode::Complex, &mut Vec::new()).unwrap();
+        program.evaluate(&[z], &[])
+    }
+
+    fn error(source: &str, mode: ExpressionMode) -> String {
+        compile(source, mode, &mut Vec::new()).unwrap_err()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn tokenizes_numbers_names_and_symbols() {
+        let tokens = tokenize("2.5*x_1 + (y)").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (0, Token::Number(2.5)),
+                (3, Token::Symbol('*')),
+                (4, Token::Identifier("x_1".to_owned())),
+                (8, Token::Symbol('+')),
+                (10, Token::Symbol('(')),
+                (11, Token::Identifier("y".to_owned())),
+                (12, Token::Symbol(')')),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_precedence() {
+        assert_eq!(real("1 + 2 * 3", 0.0, 0.0, &[]), 7.0);
+        assert_eq!(real("(1 + 2) * 3", 0.0, 0.0, &[]), 9.0);
+        assert_eq!(real("8 / 4 / 2", 0.0, 0.0, &[]), 1.0);
+        assert_eq!(real("10 - 4 - 3", 0.0, 0.0, &[]), 3.0);
+        assert_eq!(real("2 * 3 ^ 2", 0.0, 0.0, &[]), 18.0);
+        assert_eq!(real("2 ^ 3 ^ 2", 0.0, 0.0, &[]), 512.0);
+    }
+
+    #[test]
+    fn applies_unary_minus() {
+        assert_eq!(real("-x", 2.0, 0.0, &[]), -2.0);
+        assert_eq!(real("--x", 2.0, 0.0, &[]), 2.0);
+        assert_eq!(real("-x^2", 3.0, 0.0, &[]), -9.0);
+        assert_eq!(real("2^-1", 0.0, 0.0, &[]), 0.5);
+        assert_eq!(real("y * -x", 2.0, 3.0, &[]), -6.0);
+    }
+
+    #[test]
+    fn reads_variables_and_constants() {
+        assert_eq!(real("x - y", 5.0, 2.0, &[]), 3.0);
+        assert!(close(real("r", 3.0, 4.0, &[]), 5.0));
+        assert!(close(real("theta", 0.0, 1.0, &[]), PI / 2.0));
+        assert!(close(real("pi + e", 0.0, 0.0, &[]), PI + E));
+    }
+
+    #[test]
+    fn calls_functions() {
+        assert!(close(real("sin(x) + cos(0)", PI / 2.0, 0.0, &[]), 2.0));
+        assert!(close(real("atan2(y, x)", -1.0, 0.0, &[]), PI));
+        assert_eq!(real("floor(x) * abs(y)", 2.7, -3.0, &[]), 6.0);
+        assert!(close(real("sqrt(exp(ln(16)))", 0.0, 0.0, &[]), 4.0));
+    }
+
+    #[test]
+    fn discovers_parameters_once_in_order() {
+        let mut names = vec!["a".to_owned()];
+        let program = compile("b * x + a + b", ExpressionMode::Real, &mut names).unwrap();
+        assert_eq!(names, vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(program.evaluate(&[2.0, 0.0, 0.0, 0.0], &[1.0, 3.0]), 10.0);
+    }
+
+    #[test]
+    fn evaluates_complex_formulas() {
+        let z = Complex::new(1.0, 2.0);
+        assert_eq!(complex("z * z", z), z * z);
+        assert_eq!(complex("z + i", z), Complex::new(1.0, 3.0));
+        assert_eq!(complex("conj(z)", z), Complex::new(1.0, -2.0));
+        assert_eq!(complex("re(z) + im(z)", z), Complex::new(3.0, 0.0));
+        let squared = complex("z ^ 2", z);
+        assert!(close(squared.re, -3.0) && close(squared.im, 4.0), "{squared}");
+    }
+
+    #[test]
+    fn keeps_functions_to_their_mode() {
+        assert_eq!(error("conj(x)", ExpressionMode::Real), "unknown function 'conj' at column 1");
+        assert_eq!(error("atan2(z, z)", ExpressionMode::Complex), "unknown function 'atan2' at column 1");
+        // `i` is only the imaginary unit in complex mode; in real mode it is a parameter.
+        let mut names = Vec::new();
+        compile("i * x", ExpressionMode::Real, &mut names).unwrap();
+        assert_eq!(names, vec!["i".to_owned()]);
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        let real = ExpressionMode::Real;
+        assert_eq!(error("", real), "the formula is empty");
+        assert_eq!(error("x +", real), "the formula ends too early");
+        assert_eq!(error("(x + y", real), "expected ')' at the end");
+        assert_eq!(error("x y", real), "unexpected input at column 3");
+        assert_eq!(error("x $ y", real), "unexpected '$' at column 3");
+        assert_eq!(error("1.2.3", real), "'1.2.3' at column 1 is not a number");
+        assert_eq!(error("* x", real), "unexpected '*' at column 1");
+        assert_eq!(error("sin + x", real), "'sin' at column 1 needs parentheses");
+        assert_eq!(error("atan2(y)", real), "expected ',' at column 8");
+    }
+
+    #[test]
+    fn sizes_the_stack() {
+        let program = compile("x + (y * (x - y))", ExpressionMode::Real, &mut Vec::new()).unwrap();
+        assert_eq!(program.stack_size, 4);
+        let program = compile("-x", ExpressionMode::Real, &mut Vec::new()).unwrap();
+        assert_eq!(program.stack_size, 1);
+    }
+}
@@ -0,0 +1,192 @@
+use ndarray::{Array2, Array3};
+use strum::EnumIter;
+
+use crate::render::Accumulator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum DownsampleFilter {
+    Box,
+    Gaussian,
+}
+
+impl DownsampleFilter {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            DownsampleFilter::Box => "Box",
+            DownsampleFilter::Gaussian => "Gaussian",
+        }
+    }
+}
+
+/// Post-processing applied to every render before it is displayed or exported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterSettings {
+    /// The chaos game runs at this multiple of the output size in each direction.
+    pub oversample: usize,
+    pub downsample: DownsampleFilter,
+    /// Blur radius in output pixels for pixels hit only once. Zero turns density estimation off.
+    pub estimator_radius: f32,
+    /// The radius never shrinks below this, however dense a pixel is.
+    pub estimator_min_radius: f32,
+    /// How quickly the radius shrinks as the density grows.
+    pub estimator_curve: f32,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self {
+            oversample: 1,
+            downsample: DownsampleFilter::Box,
+            estimator_radius: 0.0,
+            estimator_min_radius: 0.0,
+            estimator_curve: 0.4,
+        }
+    }
+}
+
+impl FilterSettings {
+    /// Brings an accumulator rendered at `oversample` times the output size back down and
+    /// blurs it. Totals are preserved, so the result tone maps like an unfiltered render.
+    pub fn apply(&self, accumulator: Accumulator) -> Accumulator {
+        let non_finite = accumulator.non_finite;
+        let mut accumulator = if self.oversample > 1 {
+            downsample(&accumulator, self.oversample, self.downsample)
+        } else {
+            accumulator
+        };
+        if self.estimator_radius > 0.0 {
+            accumulator = density_estimation(
+                &accumulator,
+                self.estimator_radius,
+                self.estimator_min_radius,
+                self.estimator_curve,
+            );
+        }
+        accumulator.non_finite = non_finite;
+        accumulator
+    }
+}
+
+fn gaussian(distance_squared: f32, sigma: f32) -> f32 {
+    (-distance_squared / (2.0 * sigma * sigma)).exp()
+}
+
+/// Shrinks `accumulator` by `factor` in each direction, summing each block of source pixels.
+pub fn downsample(accumulator: &Accumulator, factor: usize, filter: DownsampleFilter) -> Accumulator {
+    let width = accumulator.width / factor;
+    let height = accumulator.height / factor;
+    let mut result = Accumulator::new(width, height);
+
+    // The Gaussian reaches half a block past each edge so that neighbouring blocks blend.
+    let (reach, sigma) = match filter {
+        DownsampleFilter::Box => (0, 1.0),
+        DownsampleFilter::Gaussian => (factor / 2, factor as f32 / 2.0),
+    };
+    let mut kernel = Array2::<f32>::zeros((factor + 2 * reach, factor + 2 * reach));
+    let center = (factor + 2 * reach) as f32 / 2.0;
+    for ((row, col), weight) in kernel.indexed_iter_mut() {
+        *weight = match filter {
+            DownsampleFilter::Box => 1.0,
+            DownsampleFilter::Gaussian => {
+                let dy = row as f32 + 0.5 - center;
+                let dx = col as f32 + 0.5 - center;
+                gaussian(dx * dx + dy * dy, sigma)
+            }
+        };
+    }
+    // Every source pixel contributes one unit in total, as with the box filter.
+    kernel *= (factor * factor) as f32 / kernel.sum();
+
+    for row in 0..height {
+        for col in 0..width {
+            for ((k_row, k_col), &weight) in kernel.indexed_iter() {
+                let source_row = (row * factor + k_row) as isize - reach as isize;
+                let source_col = (col * factor + k_col) as isize - reach as isize;
+                if source_row < 0
+                    || source_col < 0
+                    || source_row >= accumulator.height as isize
+                    || source_col >= accumulator.width as isize
+                {
+                    continue;
+                }
+                let (source_row, source_col) = (source_row as usize, source_col as usize);
+                for channel in 0..3 {
+                    result.radiance[[row, col, channel]] +=
+                        weight * accumulator.radiance[[source_row, source_col, channel]];
+                }
+                result.hits[[row, col]] += weight * accumulator.hits[[source_row, source_col]];
+            }
+        }
+    }
+    result
+}
+
+/// Spreads each pixel over a Gaussian whose radius is `max_radius / hits^curve`, so sparse
+/// regions are smoothed heavily while dense, well sampled regions keep their detail.
+pub fn density_estimation(
+    accumulator: &Accumulator,
+    max_radius: f32,
+    min_radius: f32,
+    curve: f32,
+) -> Accumulator {
+    let (width, height) = (accumulator.width, accumulator.height);
+    let mut radiance = Array3::<f32>::zeros((height, width, 3));
+    let mut hits = Array2::<f32>::zeros((height, width));
+
+    for ((row, col), &count) in accumulator.hits.indexed_iter() {
+        if count <= 0.0 {
+            continue;
+        }
+        let radius = (max_radius / count.powf(curve)).max(min_radius);
+        if radius < 0.5 {
+            for channel in 0..3 {
+                radiance[[row, col, channel]] += accumulator.radiance[[row, col, channel]];
+            }
+            hits[[row, col]] += count;
+            continue;
+        }
+
+        let sigma = radius / 2.0;
+        let extent = radius.ceil() as isize;
+        let mut total = 0.0;
+        for dy in -extent..=extent {
+            for dx in -extent..=extent {
+                let distance_squared = (dx * dx + dy * dy) as f32;
+                if distance_squared <= radius * radius {
+                    total += gaussian(distance_squared, sigma);
+                }
+            }
+        }
+
+        for dy in -extent..=extent {
+            for dx in -extent..=extent {
+                let distance_squared = (dx * dx + dy * dy) as f32;
+                let target_row = row as isize + dy;
+                let target_col = col as isize + dx;
+                if distance_squared > radius * radius
+                    || target_row < 0
+                    || target_col < 0
+                    || target_row >= height as isize
+                    || target_col >= width as isize
+                {
+                    continue;
+                }
+                let weight = gaussian(distance_squared, sigma) / total;
+                let (target_row, target_col) = (target_row as usize, target_col as usize);
+                for channel in 0..3 {
+                    radiance[[target_row, target_col, channel]] +=
+                        weight * accumulator.radiance[[row, col, channel]];
+                }
+                hits[[target_row, target_col]] += weight * count;
+            }
+        }
+    }
+
+    Accumulator {
+        width,
+        height,
+        radiance,
+        hits,
+        non_finite: accumulator.non_finite,
+    }
+}
@@ -0,0 +1,417 @@
+//! Finding an IFS whose attractor looks like a given image, using the collage theorem: if
+//! the union of the transformed copies of a shape is close to the shape itself, so is the
+//! attractor. The search runs on a worker thread, so this is only built for native targets.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use barnsley::util::Point;
+use egui::{Color32, ColorImage, Pos2, Rect, Sense, Shape, Stroke, TextureHandle, TextureOptions, Ui, Vec2};
+use rand::Rng;
+
+use crate::diagnostics::spectral_norm;
+use crate::transform::Affine;
+
+/// Cells per side of the grid the target and collages are compared on, spanning `[-1, 1]^2`.
+const GRID: usize = 64;
+/// Annealing steps between progress reports.
+const BATCH: usize = 200;
+/// Transforms stretching distances by more than this are penalized.
+const MAX_CONTRACTION: f32 = 0.9;
+
+/// The shape to fit, as a mask on the grid.
+pub struct Target {
+    inside: Vec<bool>,
+    /// Distance in cells from each cell to the nearest cell of the shape.
+    distance: Vec<f32>,
+    /// Points spread over the shape, four per cell, that the transforms are applied to.
+    points: Vec<Point>,
+}
+
+impl Target {
+    /// Loads an image and takes its bright pixels, or its dark ones when `invert` is set, as
+    /// the shape. The image is scaled to fit the grid and centered on it.
+    pub fn load(path: &Path, invert: bool) -> Result<Self, String> {
+        let image = image::open(path).map_err(|error| error.to_string())?;
+        let luma = image.resize(GRID as u32, GRID as u32, image::imageops::FilterType::Triangle).to_luma8();
+        let (left, top) = ((GRID - luma.width() as usize) / 2, (GRID - luma.height() as usize) / 2);
+        let mut inside = vec![false; GRID * GRID];
+        for (x, y, pixel) in luma.enumerate_pixels() {
+            let bright = pixel.0[0] >= 128;
+            inside[(top + y as usize) * GRID + left + x as usize] = bright != invert;
+        }
+        if !inside.contains(&true) {
+            return Err("The image has no pixels on the chosen side of the threshold.".to_owned());
+        }
+        let mut points = Vec::new();
+        for (cell, _) in inside.iter().enumerate().filter(|(_, &inside)| inside) {
+            let (row, col) = (cell / GRID, cell % GRID);
+            for (dx, dy) in [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)] {
+                points.push(Point {
+                    x: -1.0 + 2.0 * (col as f32 + dx) / GRID as f32,
+                    y: 1.0 - 2.0 * (row as f32 + dy) / GRID as f32,
+                });
+            }
+        }
+        Ok(Self {
+            distance: distance_transform(&inside),
+            inside,
+            points,
+        })
+    }
+
+    /// How far the collage of the target under `maps` is from the target: the mean distance
+    /// from collage cells to the target plus the mean distance from target cells to the
+    /// collage, in units of the canvas, with a penalty for maps that do not contract.
+    pub fn loss(&self, maps: &[Affine]) -> f32 {
+        let mut collage = vec![false; GRID * GRID];
+        for map in maps {
+            for &point in &self.points {
+                if let Some(cell) = cell(map.apply(point)) {
+                    collage[cell] = true;
+                }
+            }
+        }
+        let collage_distance = distance_transform(&collage);
+        let mean = |mask: &[bool], distance: &[f32]| {
+            let (sum, count) = mask
+                .iter()
+                .zip(distance)
+                .filter(|(&inside, _)| inside)
+                .fold((0.0, 0), |(sum, count), (_, &distance)| (sum + distance, count + 1));
+            if count == 0 {
+                GRID as f32
+            } else {
+                sum / count as f32
+            }
+        };
+        let distance = mean(&collage, &self.distance) + mean(&self.inside, &collage_distance);
+        let penalty: f32 = maps
+            .iter()
+            .map(|map| (spectral_norm(map.a, map.b, map.c, map.d) - MAX_CONTRACTION).max(0.0))
+            .sum();
+        2.0 * distance / GRID as f32 + 10.0 * penalty
+    }
+
+    /// A few contracted copies of the whole canvas, each centered on a random point of the shape.
+    pub fn initial_guess(&self, count: usize, rng: &mut impl Rng) -> Vec<Affine> {
+        (0..count)
+            .map(|_| {
+                let center = self.points[rng.gen_range(0..self.points.len())];
+                let (sin, cos) = rng.gen_range(-0.5f32..0.5).sin_cos();
+                let scale = 0.5;
+                Affine {
+                    a: scale * cos,
+                    b: -scale * sin,
+                    c: scale * sin,
+                    d: scale * cos,
+                    xshift: center.x * (1.0 - scale),
+                    yshift: center.y * (1.0 - scale),
+                }
+            })
+            .collect()
+    }
+
+    fn image(&self) -> ColorImage {
+        let pixels = self
+            .inside
+            .iter()
+            .map(|&inside| if inside { Color32::from_gray(200) } else { Color32::from_gray(30) })
+            .collect();
+        ColorImage { size: [GRID, GRID], pixels }
+    }
+}
+
+fn cell(point: Point) -> Option<usize> {
+    let col = ((point.x + 1.0) / 2.0 * GRID as f32).floor();
+    let row = ((1.0 - point.y) / 2.0 * GRID as f32).floor();
+    let range = 0.0..GRID as f32;
+    (range.contains(&col) && range.contains(&row)).then(|| row as usize * GRID + col as usize)
+}
+
+/// Approximate Euclidean distance from every cell to the nearest set cell, by two chamfer
+/// passes. Without any set cell every distance is the size of the grid.
+fn distance_transform(mask: &[bool]) -> Vec<f32> {
+    const DIAGONAL: f32 = std::f32::consts::SQRT_2;
+    let mut distance: Vec<f32> = mask.iter().map(|&set| if set { 0.0 } else { GRID as f32 }).collect();
+    let at = |row: usize, col: usize| row * GRID + col;
+    for row in 0..GRID {
+        for col in 0..GRID {
+            let mut best = distance[at(row, col)];
+            if col > 0 {
+                best = best.min(distance[at(row, col - 1)] + 1.0);
+            }
+            if row > 0 {
+                best = best.min(distance[at(row - 1, col)] + 1.0);
+                if col > 0 {
+                    best = best.min(distance[at(row - 1, col - 1)] + DIAGONAL);
+                }
+                if col + 1 < GRID {
+                    best = best.min(distance[at(row - 1, col + 1)] + DIAGONAL);
+                }
+            }
+            distance[at(row, col)] = best;
+        }
+    }
+    for row in (0..GRID).rev() {
+        for col in (0..GRID).rev() {
+            let mut best = distance[at(row, col)];
+            if col + 1 < GRID {
+                best = best.min(distance[at(row, col + 1)] + 1.0);
+            }
+            if row + 1 < GRID {
+                best = best.min(distance[at(row + 1, col)] + 1.0);
+                if col + 1 < GRID {
+                    best = best.min(distance[at(row + 1, col + 1)] + DIAGONAL);
+                }
+                if col > 0 {
+                    best = best.min(distance[at(row + 1, col - 1)] + DIAGONAL);
+                }
+            }
+            distance[at(row, col)] = best;
+        }
+    }
+    distance
+}
+
+fn coefficient(map: &mut Affine, index: usize) -> &mut f32 {
+    match index {
+        0 => &mut map.a,
+        1 => &mut map.b,
+        2 => &mut map.c,
+        3 => &mut map.d,
+        4 => &mut map.xshift,
+        _ => &mut map.yshift,
+    }
+}
+
+/// What the worker has found so far.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub best: Vec<Affine>,
+    pub best_loss: f32,
+    /// Best loss after each batch of steps.
+    pub losses: Vec<f32>,
+    pub steps: usize,
+    pub finished: bool,
+}
+
+/// Simulated annealing: each step nudges one coefficient by an amount that shrinks with the
+/// temperature, keeping worse systems now and then while the temperature is high. Runs until
+/// `stop` is set or the temperature reaches its floor.
+fn anneal(target: Arc<Target>, mut current: Vec<Affine>, progress: Arc<Mutex<Progress>>, stop: Arc<AtomicBool>) {
+    let mut rng = rand::thread_rng();
+    let mut current_loss = target.loss(&current);
+    let mut best = current.clone();
+    let mut best_loss = current_loss;
+    let mut temperature = 0.05 * current_loss.max(f32::EPSILON);
+    let start_temperature = temperature;
+    let floor = 1e-4 * start_temperature;
+    while !stop.load(Ordering::Relaxed) && temperature > floor {
+        for _ in 0..BATCH {
+            let mut candidate = current.clone();
+            let step = 0.01 + 0.2 * temperature / start_temperature;
+            let map = rng.gen_range(0..candidate.len());
+            *coefficient(&mut candidate[map], rng.gen_range(0..6)) += rng.gen_range(-step..step);
+            let loss = target.loss(&candidate);
+            if loss < current_loss || rng.gen::<f32>() < ((current_loss - loss) / temperature).exp() {
+                current = candidate;
+                current_loss = loss;
+                if loss < best_loss {
+                    best = current.clone();
+                    best_loss = loss;
+                }
+            }
+            temperature = (temperature * 0.9995).max(floor);
+        }
+        let mut progress = progress.lock().unwrap();
+        progress.best.clone_from(&best);
+        progress.best_loss = best_loss;
+        progress.losses.push(best_loss);
+        progress.steps += BATCH;
+    }
+    progress.lock().unwrap().finished = true;
+}
+
+/// A search in progress.
+struct Run {
+    progress: Arc<Mutex<Progress>>,
+    stop: Arc<AtomicBool>,
+}
+
+/// The fitting window's state.
+pub struct Fitting {
+    target: Option<Arc<Target>>,
+    texture: Option<TextureHandle>,
+    error: Option<String>,
+    invert: bool,
+    transform_count: usize,
+    run: Option<Run>,
+}
+
+impl Default for Fitting {
+    fn default() -> Self {
+        Self {
+            target: None,
+            texture: None,
+            error: None,
+            invert: false,
+            transform_count: 3,
+            run: None,
+        }
+    }
+}
+
+impl Fitting {
+    fn start(&mut self) {
+        self.stop();
+        let Some(target) = self.target.clone() else {
+            return;
+        };
+        let initial = target.initial_guess(self.transform_count, &mut rand::thread_rng());
+        let progress = Arc::new(Mutex::new(Progress {
+            best_loss: target.loss(&initial),
+            best: initial.clone(),
+            ..Progress::default()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (worker_progress, worker_stop) = (progress.clone(), stop.clone());
+        thread::spawn(move || anneal(target, initial, worker_progress, worker_stop));
+        self.run = Some(Run { progress, stop });
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(run) = &self.run {
+            run.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the best maps found when the user accepts them.
+    pub fn ui(&mut self, ui: &mut Ui) -> Option<Vec<Affine>> {
+        ui.horizontal(|ui| {
+            if ui.button("Load target image").clicked() {
+                if let Some(path) = rfd::FileDialog::new().add_filter("png", &["png"]).pick_file() {
+                    self.stop();
+                    self.run = None;
+                    match Target::load(&path, self.invert) {
+                        Ok(target) => {
+                            let image = target.image();
+                            self.texture = Some(ui.ctx().load_texture("fitting_target", image, TextureOptions::NEAREST));
+                            self.target = Some(Arc::new(target));
+                            self.error = None;
+                        }
+                        Err(error) => self.error = Some(error),
+                    }
+                }
+            }
+            ui.checkbox(&mut self.invert, "Dark pixels are the shape")
+                .on_hover_text("Takes effect when the next image is loaded");
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        let Some(texture) = self.texture.as_ref().filter(|_| self.target.is_some()) else {
+            ui.label("Load an image of the shape to fit; bright pixels are taken as the shape.");
+            return None;
+        };
+
+        let progress = self
+            .run
+            .as_ref()
+            .map(|run| run.progress.lock().unwrap().clone());
+        let running = matches!(&self.run, Some(run) if !run.stop.load(Ordering::Relaxed))
+            && matches!(&progress, Some(progress) if !progress.finished);
+        ui.add_enabled(
+            !running,
+            egui::Slider::new(&mut self.transform_count, 1..=8).text("Affine transforms"),
+        );
+
+        let size = Vec2::splat(ui.available_width().min(256.0));
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        ui.painter().image(
+            texture.id(),
+            rect,
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+            Color32::WHITE,
+        );
+        if let Some(progress) = &progress {
+            paint_maps(ui, rect, &progress.best);
+            ui.label(format!(
+                "Loss {:.4} after {} steps",
+                progress.best_loss, progress.steps
+            ));
+            loss_plot(ui, &progress.losses);
+        }
+
+        let mut accepted = None;
+        ui.horizontal(|ui| {
+            if running {
+                if ui.button("Stop").clicked() {
+                    self.stop();
+                }
+                ui.ctx().request_repaint();
+            } else if ui.button("Start").clicked() {
+                self.start();
+            }
+            let best = progress.filter(|progress| !progress.best.is_empty());
+            if ui.add_enabled(best.is_some(), egui::Button::new("Accept")).clicked() {
+                self.stop();
+                accepted = best.map(|progress| progress.best);
+            }
+        });
+        accepted
+    }
+}
+
+impl Drop for Fitting {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Outlines the image of the grid's square under each map.
+fn paint_maps(ui: &Ui, rect: Rect, maps: &[Affine]) {
+    let painter = ui.painter_at(rect);
+    let to_screen = |point: Point| {
+        Pos2::new(
+            rect.left() + (point.x + 1.0) / 2.0 * rect.width(),
+            rect.top() + (1.0 - point.y) / 2.0 * rect.height(),
+        )
+    };
+    for map in maps {
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|&(x, y)| to_screen(map.apply(Point { x, y })))
+            .collect();
+        painter.add(Shape::closed_line(corners, Stroke::new(1.5, Color32::from_rgb(240, 160, 40))));
+    }
+}
+
+/// The best loss so far against the number of batches, on a logarithmic scale.
+fn loss_plot(ui: &mut Ui, losses: &[f32]) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::new(ui.available_width(), 100.0), Sense::hover());
+    ui.painter()
+        .rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+    if losses.len() < 2 {
+        return;
+    }
+    let logs: Vec<f32> = losses.iter().map(|loss| loss.max(1e-6).ln()).collect();
+    let (low, high) = logs
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(low, high), &value| (low.min(value), high.max(value)));
+    let inner = rect.shrink(4.0);
+    let points = logs
+        .iter()
+        .enumerate()
+        .map(|(index, &value)| {
+            Pos2::new(
+                inner.left() + index as f32 / (logs.len() - 1) as f32 * inner.width(),
+                inner.bottom() - (value - low) / (high - low).max(f32::EPSILON) * inner.height(),
+            )
+        })
+        .collect();
+    ui.painter()
+        .add(Shape::line(points, Stroke::new(1.5, ui.visuals().text_color())));
+}
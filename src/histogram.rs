@@ -0,0 +1,161 @@
+use barnsley::util::Color;
+use egui::{Color32, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
+
+use crate::render::Accumulator;
+
+/// Bins for the color histogram, spread evenly over `[0, 1]`.
+const COLOR_BINS: usize = 64;
+
+/// Distribution of densities and final colors in a render, for tuning tone mapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histograms {
+    /// The tone mapping divisor the image was made with.
+    pub scale: usize,
+    /// Pixel counts by hits: bin 0 holds the empty pixels, bin 1 those with `0 < hits < 2`
+    /// and bin `k` above that those with `2^(k - 1) <= hits < 2^k`. Filtering leaves
+    /// fractional counts, so any pixel with more than zero hits counts as hit.
+    pub hits: Vec<usize>,
+    /// Pixel counts of each final 8-bit channel value, grouped into `COLOR_BINS` bins.
+    pub channels: [Vec<usize>; 3],
+    /// Fraction of pixels hit at all.
+    pub coverage: f32,
+    /// Fraction of pixels whose linear value exceeds 1 and is clipped, per channel.
+    pub clipped: [f32; 3],
+    /// Fraction of pixels dense enough to be fully opaque.
+    pub opaque: f32,
+}
+
+impl Histograms {
+    pub fn new(accumulator: &Accumulator, scale: usize, background: Option<Color>) -> Self {
+        let pixels = (accumulator.width * accumulator.height).max(1) as f32;
+        let mut hits = vec![0usize];
+        let mut covered = 0;
+        let mut opaque = 0;
+        for &count in accumulator.hits.iter() {
+            let bin = if count <= 0.0 {
+                0
+            } else {
+                covered += 1;
+                count.log2().floor().max(0.0) as usize + 1
+            };
+            if bin >= hits.len() {
+                hits.resize(bin + 1, 0);
+            }
+            hits[bin] += 1;
+            if count >= scale as f32 {
+                opaque += 1;
+            }
+        }
+
+        let linear = accumulator.linear(scale);
+        let mut clipped = [0.0; 3];
+        for (channel, clipped) in clipped.iter_mut().enumerate() {
+            let count = linear
+                .index_axis(ndarray::Axis(2), channel)
+                .iter()
+                .filter(|&&value| value > 1.0)
+                .count();
+            *clipped = count as f32 / pixels;
+        }
+
+        let composite = accumulator.composite(scale, background);
+        let mut channels = [vec![0; COLOR_BINS], vec![0; COLOR_BINS], vec![0; COLOR_BINS]];
+        for ((_, _, channel), &value) in composite.indexed_iter() {
+            if channel < 3 {
+                let bin = ((value * COLOR_BINS as f32) as usize).min(COLOR_BINS - 1);
+                channels[channel][bin] += 1;
+            }
+        }
+
+        Self {
+            scale,
+            hits,
+            channels,
+            coverage: covered as f32 / pixels,
+            clipped,
+            opaque: opaque as f32 / pixels,
+        }
+    }
+
+    pub fn ui(&self, ui: &mut Ui) {
+        egui::Grid::new("histogram_stats").num_columns(2).striped(true).show(ui, |ui| {
+            ui.label("Effective save_scale");
+            ui.label(self.scale.to_string())
+                .on_hover_text("Radiance is divided by this before display, so a pixel hit this often is fully opaque.");
+            ui.end_row();
+            ui.label("Pixels hit");
+            ui.label(format!("{:.2}%", self.coverage * 100.0));
+            ui.end_row();
+            ui.label("Pixels fully opaque");
+            ui.label(format!("{:.2}%", self.opaque * 100.0));
+            ui.end_row();
+            for (name, clipped) in ["red", "green", "blue"].iter().zip(self.clipped) {
+                ui.label(format!("Clipped {name}"));
+                ui.label(format!("{:.2}%", clipped * 100.0));
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.label("Pixels by hit count (bins double in width, heights are logarithmic)");
+        let rect = plot_rect(ui);
+        let max = log_height(self.hits.iter().copied().max().unwrap_or(0));
+        let bar_width = rect.width() / self.hits.len() as f32;
+        let color = ui.visuals().text_color();
+        for (bin, &count) in self.hits.iter().enumerate() {
+            let height = log_height(count) / max.max(f32::EPSILON) * rect.height();
+            let left = rect.left() + bin as f32 * bar_width;
+            let bar = Rect::from_min_max(
+                Pos2::new(left + 1.0, rect.bottom() - height),
+                Pos2::new(left + bar_width - 1.0, rect.bottom()),
+            );
+            let label = match bin {
+                0 => "0 hits".to_owned(),
+                1 => "under 2 hits".to_owned(),
+                _ => format!("{} to {} hits", 1u64 << (bin - 1), (1u64 << bin) - 1),
+            };
+            ui.painter().rect_filled(bar, 0.0, color.gamma_multiply(0.6));
+            ui.interact(bar, ui.id().with(("hits", bin)), Sense::hover())
+                .on_hover_text(format!("{label}: {count} pixels"));
+        }
+
+        ui.separator();
+        ui.label("Final color values (heights are logarithmic)");
+        let rect = plot_rect(ui);
+        let max = self
+            .channels
+            .iter()
+            .flat_map(|channel| channel.iter().copied())
+            .max()
+            .map_or(0.0, log_height);
+        let colors = [
+            Color32::from_rgb(230, 70, 70),
+            Color32::from_rgb(70, 200, 70),
+            Color32::from_rgb(80, 120, 240),
+        ];
+        for (channel, color) in self.channels.iter().zip(colors) {
+            let points = channel
+                .iter()
+                .enumerate()
+                .map(|(bin, &count)| {
+                    Pos2::new(
+                        rect.left() + (bin as f32 + 0.5) / COLOR_BINS as f32 * rect.width(),
+                        rect.bottom() - log_height(count) / max.max(f32::EPSILON) * rect.height(),
+                    )
+                })
+                .collect();
+            ui.painter().add(Shape::line(points, Stroke::new(1.5, color)));
+        }
+    }
+}
+
+fn log_height(count: usize) -> f32 {
+    (count as f32).ln_1p()
+}
+
+fn plot_rect(ui: &mut Ui) -> Rect {
+    let (rect, _) = ui.allocate_exact_size(Vec2::new(ui.available_width(), 120.0), Sense::hover());
+    ui.painter()
+        .rect_stroke(rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+    rect.shrink(4.0)
+}
@@ -0,0 +1,51 @@
+use crate::transform::Transform;
+
+/// A system of transforms, like barnsley's `IFS` but holding the editor's own [`Transform`]
+/// so that it can include the kinds barnsley does not know about.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IFS {
+    pub transforms: Vec<Transform>,
+    /// Applied to every point as it is plotted, without feeding back into the iteration.
+    pub final_transform: Option<Transform>,
+}
+
+impl IFS {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_transform(&mut self, transform: Transform) {
+        self.transforms.push(transform);
+    }
+
+    pub fn delete_transform(&mut self, index: usize) {
+        self.transforms.remove(index);
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    /// Randomizes the transforms barnsley knows about in place. The other kinds are left as
+    /// they are so that the transform info and transition matrix still line up.
+    pub fn randomize(&mut self) {
+        let mut ifs = barnsley::ifs::IFS::new();
+        for transform in &self.transforms {
+            if let Some(transform) = transform.to_barnsley() {
+                ifs.add_transform(transform);
+            }
+        }
+        ifs.randomize();
+        let slots = self.transforms.iter_mut().filter(|transform| transform.to_barnsley().is_some());
+        for (slot, transform) in slots.zip(ifs.transforms) {
+            *slot = Transform::from(transform);
+        }
+    }
+}
+
+/// Systems to blend between, spending `step_counts[i]` frames going from `ifs_vec[i]` to `ifs_vec[i + 1]`.
+pub struct AnimationSequence {
+    pub ifs_vec: Vec<IFS>,
+    pub step_counts: Vec<usize>,
+}
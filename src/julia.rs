@@ -0,0 +1,181 @@
+use barnsley::util::Color;
+use egui::{Color32, Rgba, Ui};
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::coloring::Gradient;
+use crate::ifs::IFS;
+use crate::render::Accumulator;
+use crate::transform::Transform;
+
+/// Escape radius; large so the smooth iteration count has settled.
+const BAILOUT: f32 = 256.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum JuliaShading {
+    /// Normalized iteration count, free of the bands plain escape counts give.
+    #[default]
+    SmoothIteration,
+    /// Estimated distance to the Julia set, which keeps thin filaments visible.
+    DistanceEstimate,
+}
+
+impl JuliaShading {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            JuliaShading::SmoothIteration => "Smooth iteration count",
+            JuliaShading::DistanceEstimate => "Distance estimate",
+        }
+    }
+}
+
+/// Draws the filled Julia set of `z^2 + c` instead of running the chaos game, with `c`
+/// taken from one of the inverse Julia transforms, whose attractor is that set's boundary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EscapeTime {
+    pub enabled: bool,
+    /// Index in `IFS::transforms` of the inverse Julia transform to take `c` from.
+    pub transform: usize,
+    pub max_iterations: usize,
+    pub shading: JuliaShading,
+    /// Distance, in pixels, over which the distance estimate runs through the gradient.
+    pub falloff: f32,
+    pub gradient: Gradient,
+    /// Color of points that never escape.
+    pub interior: Color,
+}
+
+impl Default for EscapeTime {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            transform: 0,
+            max_iterations: 256,
+            shading: JuliaShading::SmoothIteration,
+            falloff: 20.0,
+            gradient: Gradient::default(),
+            interior: Color { r: 0.0, g: 0.0, b: 0.0 },
+        }
+    }
+}
+
+impl EscapeTime {
+    /// The constant of the chosen transform, or of the first inverse Julia transform if the
+    /// chosen one is of another kind. `None` when there is none at all.
+    pub fn constant(&self, ifs: &IFS) -> Option<Complex<f32>> {
+        let julia = |transform: &Transform| match transform {
+            Transform::InverseJuliaTransform(t) => Some(Complex::from_polar(t.r, t.theta)),
+            _ => None,
+        };
+        ifs.transforms
+            .get(self.transform)
+            .and_then(julia)
+            .or_else(|| ifs.transforms.iter().find_map(julia))
+    }
+
+    /// Renders over the same canvas the chaos game uses. Every pixel gets `scale` hits so
+    /// the image comes out exactly in the computed colors after tone mapping.
+    pub fn render(&self, c: Complex<f32>, width: usize, height: usize, scale: usize) -> Accumulator {
+        let mut accumulator = Accumulator::new(width, height);
+        let pixels_per_unit = width.min(height) as f32 / 2.0;
+        let scale = scale as f32;
+        for row in 0..height {
+            for col in 0..width {
+                let z = Complex::new(
+                    (col as f32 + 0.5 - width as f32 / 2.0) / pixels_per_unit,
+                    (height as f32 / 2.0 - row as f32 - 0.5) / pixels_per_unit,
+                );
+                let color = self.color(z, c, pixels_per_unit);
+                accumulator.radiance[[row, col, 0]] = color.r * scale;
+                accumulator.radiance[[row, col, 1]] = color.g * scale;
+                accumulator.radiance[[row, col, 2]] = color.b * scale;
+                accumulator.hits[[row, col]] = scale;
+            }
+        }
+        accumulator
+    }
+
+    fn color(&self, mut z: Complex<f32>, c: Complex<f32>, pixels_per_unit: f32) -> Color {
+        let mut derivative = Complex::new(1.0, 0.0);
+        for iteration in 0..self.max_iterations {
+            let norm = z.norm();
+            if norm > BAILOUT {
+                let t = match self.shading {
+                    JuliaShading::SmoothIteration => {
+                        let smooth = iteration as f32 + 1.0 - norm.ln().log2();
+                        smooth.max(0.0).ln_1p() / (self.max_iterations as f32).ln_1p()
+                    }
+                    JuliaShading::DistanceEstimate => {
+                        let distance = 0.5 * norm * norm.ln() / derivative.norm();
+                        1.0 - (-distance * pixels_per_unit / self.falloff.max(f32::EPSILON)).exp()
+                    }
+                };
+                return self.gradient.sample(t);
+            }
+            derivative = 2.0 * z * derivative;
+            z = z * z + c;
+        }
+        self.interior
+    }
+
+    /// `labels` names each inverse Julia transform with its index in `IFS::transforms`.
+    /// Returns whether anything changed.
+    pub fn ui(&mut self, ui: &mut Ui, labels: &[(usize, String)]) -> bool {
+        let mut changed = ui
+            .checkbox(&mut self.enabled, "Draw the filled Julia set instead of the chaos game")
+            .changed();
+        if !self.enabled {
+            return changed;
+        }
+        if labels.is_empty() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Add an inverse Julia transform to take the constant from.",
+            );
+            return changed;
+        }
+        let selected = labels
+            .iter()
+            .find(|(index, _)| *index == self.transform)
+            .unwrap_or(&labels[0]);
+        egui::ComboBox::from_label("Constant from")
+            .selected_text(&selected.1)
+            .show_ui(ui, |ui| {
+                for (index, label) in labels {
+                    changed |= ui.selectable_value(&mut self.transform, *index, label).changed();
+                }
+            });
+        changed |= ui
+            .add(egui::Slider::new(&mut self.max_iterations, 16..=2048).logarithmic(true).text("Iterations"))
+            .changed();
+        egui::ComboBox::from_label("Shading")
+            .selected_text(self.shading.get_name())
+            .show_ui(ui, |ui| {
+                for shading in JuliaShading::iter() {
+                    changed |= ui
+                        .selectable_value(&mut self.shading, shading, shading.get_name())
+                        .changed();
+                }
+            });
+        if self.shading == JuliaShading::DistanceEstimate {
+            changed |= ui
+                .add(egui::Slider::new(&mut self.falloff, 1.0..=200.0).logarithmic(true).text("Falloff in pixels"))
+                .changed();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Interior");
+            let mut color: Color32 = Rgba::from_rgb(self.interior.r, self.interior.g, self.interior.b).into();
+            if ui.color_edit_button_srgba(&mut color).changed() {
+                self.interior = Color {
+                    r: color.r() as f32 / 255.0,
+                    g: color.g() as f32 / 255.0,
+                    b: color.b() as f32 / 255.0,
+                };
+                changed = true;
+            }
+        });
+        changed |= self.gradient.ui(ui);
+        changed
+    }
+}
@@ -1,5 +1,29 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+mod analysis;
 mod app;
+mod aspect;
+mod coloring;
+mod diagnostics;
+mod export;
+mod expression;
+mod filter;
+#[cfg(not(target_arch = "wasm32"))]
+mod fitting;
+mod histogram;
+mod ifs;
+mod julia;
+mod mandelbrot;
+mod overlay;
+mod parameters;
+mod preview;
+mod ranges;
+mod render;
+mod sweep;
+mod symmetry;
 mod transform;
+mod transitions;
+mod variation;
 pub use app::MyApp;
+#[cfg(not(target_arch = "wasm32"))]
+pub use sweep::cli as sweep_cli;
@@ -6,11 +6,20 @@
 fn main() -> eframe::Result<()> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("sweep") {
+        if let Err(error) = barnsley_gui::sweep_cli(&args[1..]) {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "Barnsley",
         native_options,
-        Box::new(|_| Box::<barnsley_gui::MyApp>::default()),
+        Box::new(|cc| Box::new(barnsley_gui::MyApp::new(cc))),
     )
 }
 
@@ -27,7 +36,7 @@ fn main() {
             .start(
                 "the_canvas_id", // hardcode it
                 web_options,
-                Box::new(|_cc| Box::<barnsley_gui::MyApp>::default()),
+                Box::new(|cc| Box::new(barnsley_gui::MyApp::new(cc))),
             )
             .await
             .expect("failed to start eframe");
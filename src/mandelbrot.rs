@@ -0,0 +1,149 @@
+use std::f32::consts::PI;
+
+use egui::{Color32, ColorImage, Pos2, Rect, Sense, Stroke, TextureHandle, TextureOptions, Ui, Vec2};
+use num_complex::Complex;
+
+/// Side of the picker in screen points, and of its texture in pixels.
+const SIZE: usize = 200;
+const MAX_ITERATIONS: usize = 128;
+
+/// The part of the complex plane the picker shows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct View {
+    center: Complex<f32>,
+    /// Width and height of the view.
+    span: f32,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            center: Complex::new(-0.5, 0.0),
+            span: 3.0,
+        }
+    }
+}
+
+impl View {
+    fn to_plane(self, rect: Rect, pos: Pos2) -> Complex<f32> {
+        let offset = (pos - rect.center()) / rect.width() * self.span;
+        self.center + Complex::new(offset.x, -offset.y)
+    }
+
+    fn to_screen(self, rect: Rect, c: Complex<f32>) -> Pos2 {
+        let offset = (c - self.center) / self.span * rect.width();
+        rect.center() + Vec2::new(offset.re, -offset.im)
+    }
+
+    fn zoom(&mut self, factor: f32, around: Complex<f32>) {
+        self.center = around + (self.center - around) / factor;
+        self.span /= factor;
+    }
+}
+
+/// The last texture drawn, remembered with the view it shows so it is only recomputed
+/// after zooming or panning.
+#[derive(Clone)]
+struct Cached {
+    view: View,
+    texture: TextureHandle,
+}
+
+/// A zoomable view of the Mandelbrot set with a marker on the constant `r e^(i theta)`.
+/// Clicking picks a new constant, dragging pans and Ctrl+scroll or the buttons zoom.
+/// Returns whether the constant changed.
+pub fn picker(ui: &mut Ui, r: &mut f32, theta: &mut f32) -> bool {
+    let id = ui.id().with("mandelbrot_picker");
+    let mut view = ui.data_mut(|data| *data.get_temp_mut_or_default::<View>(id));
+    let mut changed = false;
+
+    let (rect, response) = ui.allocate_exact_size(Vec2::splat(SIZE as f32), Sense::click_and_drag());
+    if response.dragged() {
+        let delta = response.drag_delta() / rect.width() * view.span;
+        view.center -= Complex::new(delta.x, -delta.y);
+    }
+    if response.hovered() {
+        let zoom = ui.input(|input| input.zoom_delta());
+        if zoom != 1.0 {
+            let around = response
+                .hover_pos()
+                .map_or(view.center, |pos| view.to_plane(rect, pos));
+            view.zoom(zoom, around);
+        }
+    }
+    if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+        let c = view.to_plane(rect, pos);
+        *r = c.norm();
+        *theta = c.arg().rem_euclid(2.0 * PI);
+        changed = true;
+    }
+
+    let cached = ui.data_mut(|data| data.get_temp::<Cached>(id.with("texture")));
+    let texture = match cached {
+        Some(cached) if cached.view == view => cached.texture,
+        _ => {
+            let texture = ui.ctx().load_texture("mandelbrot_picker", image(view), TextureOptions::LINEAR);
+            ui.data_mut(|data| data.insert_temp(id.with("texture"), Cached { view, texture: texture.clone() }));
+            texture
+        }
+    };
+    let painter = ui.painter_at(rect);
+    painter.image(
+        texture.id(),
+        rect,
+        Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+        Color32::WHITE,
+    );
+    let marker = view.to_screen(rect, Complex::from_polar(*r, *theta));
+    painter.circle_stroke(marker, 5.0, Stroke::new(2.0, Color32::BLACK));
+    painter.circle_stroke(marker, 4.0, Stroke::new(1.5, Color32::WHITE));
+    response.on_hover_text("Click to pick the constant, drag to pan, Ctrl+scroll to zoom");
+
+    ui.horizontal(|ui| {
+        if ui.small_button("Zoom in").clicked() {
+            view.zoom(2.0, view.center);
+        }
+        if ui.small_button("Zoom out").clicked() {
+            view.zoom(0.5, view.center);
+        }
+        if ui.small_button("Center on constant").clicked() {
+            view.center = Complex::from_polar(*r, *theta);
+        }
+        if ui.small_button("Reset").clicked() {
+            view = View::default();
+        }
+    });
+    ui.data_mut(|data| data.insert_temp(id, view));
+    changed
+}
+
+/// Escape-time rendering of the set, black inside and shaded by smooth iteration count outside.
+fn image(view: View) -> ColorImage {
+    let mut pixels = Vec::with_capacity(SIZE * SIZE);
+    for row in 0..SIZE {
+        for col in 0..SIZE {
+            let offset = Complex::new(col as f32 + 0.5, SIZE as f32 / 2.0 - row as f32 - 0.5) / SIZE as f32;
+            let c = view.center + Complex::new(offset.re - 0.5, offset.im) * view.span;
+            pixels.push(shade(c));
+        }
+    }
+    ColorImage {
+        size: [SIZE, SIZE],
+        pixels,
+    }
+}
+
+fn shade(c: Complex<f32>) -> Color32 {
+    let mut z = Complex::<f32>::new(0.0, 0.0);
+    for iteration in 0..MAX_ITERATIONS {
+        let norm = z.norm();
+        if norm > 16.0 {
+            let smooth = iteration as f32 + 1.0 - norm.ln().log2();
+            let t = (smooth.max(0.0).ln_1p() / (MAX_ITERATIONS as f32).ln_1p()).min(1.0);
+            let value = (t.sqrt() * 255.0) as u8;
+            return Color32::from_rgb(value / 3, value / 2 + 40, value);
+        }
+        z = z * z + c;
+    }
+    Color32::BLACK
+}
@@ -0,0 +1,240 @@
+use egui::{Color32, Id, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2};
+
+use crate::coloring::{OrbitTrap, TrapShape};
+use crate::transform::{base_color, Transform};
+
+const HANDLE_SIZE: f32 = 10.0;
+
+/// Maps between the chaos game's coordinates and the preview on screen, matching the
+/// layout used by `Accumulator`: the shorter side spans `[-1, 1]` and y points up.
+#[derive(Clone, Copy)]
+pub struct CanvasMapping {
+    pub rect: Rect,
+    pub pixels_per_unit: f32,
+}
+
+impl CanvasMapping {
+    pub fn new(rect: Rect, width: usize, height: usize) -> Self {
+        let screen_per_pixel = rect.width() / width as f32;
+        Self {
+            rect,
+            pixels_per_unit: width.min(height) as f32 / 2.0 * screen_per_pixel,
+        }
+    }
+
+    pub fn to_screen(self, x: f32, y: f32) -> Pos2 {
+        self.rect.center() + Vec2::new(x, -y) * self.pixels_per_unit
+    }
+
+    pub fn to_world(self, pos: Pos2) -> (f32, f32) {
+        let offset = (pos - self.rect.center()) / self.pixels_per_unit;
+        (offset.x, -offset.y)
+    }
+}
+
+/// The parts of a transform that act like `(x, y) -> (a x + b y + xshift, c x + d y + yshift)`.
+struct Frame<'a> {
+    a: &'a mut f32,
+    b: &'a mut f32,
+    c: &'a mut f32,
+    d: &'a mut f32,
+    shift: Option<(&'a mut f32, &'a mut f32)>,
+}
+
+impl<'a> Frame<'a> {
+    fn from_transform(transform: &'a mut Transform) -> Option<Self> {
+        match transform {
+            Transform::LinearTransform(t) => Some(Frame {
+                a: &mut t.a,
+                b: &mut t.b,
+                c: &mut t.c,
+                d: &mut t.d,
+                shift: None,
+            }),
+            Transform::AffineTransform(t) => Some(Frame {
+                a: &mut t.a,
+                b: &mut t.b,
+                c: &mut t.c,
+                d: &mut t.d,
+                shift: Some((&mut t.xshift, &mut t.yshift)),
+            }),
+            // The pre-affine decides where the variations are sampled, so it is the part
+            // worth dragging around.
+            Transform::CompositeTransform(t) => Some(Frame {
+                a: &mut t.pre.a,
+                b: &mut t.pre.b,
+                c: &mut t.pre.c,
+                d: &mut t.pre.d,
+                shift: Some((&mut t.pre.xshift, &mut t.pre.yshift)),
+            }),
+            _ => None,
+        }
+    }
+
+    fn origin(&self) -> (f32, f32) {
+        match &self.shift {
+            Some((x, y)) => (**x, **y),
+            None => (0.0, 0.0),
+        }
+    }
+}
+
+/// Draws the image of the unit square under every linear and affine transform, with
+/// handles to drag the translation, either basis vector, or the rotation. Returns
+/// whether any coefficient changed.
+pub fn transform_overlay(ui: &mut Ui, mapping: CanvasMapping, transforms: &mut [Transform]) -> bool {
+    let mut changed = false;
+    let painter = ui.painter_at(mapping.rect);
+
+    for (index, transform) in transforms.iter_mut().enumerate() {
+        let color = base_color(transform);
+        let color = Color32::from_rgb(
+            (color.r * 255.0) as u8,
+            (color.g * 255.0) as u8,
+            (color.b * 255.0) as u8,
+        );
+        let Some(mut frame) = Frame::from_transform(transform) else {
+            continue;
+        };
+        let id = Id::new("transform_overlay").with(index);
+
+        let (ox, oy) = frame.origin();
+        let origin = mapping.to_screen(ox, oy);
+        let first = mapping.to_screen(ox + *frame.a, oy + *frame.c);
+        let second = mapping.to_screen(ox + *frame.b, oy + *frame.d);
+        let far = mapping.to_screen(ox + *frame.a + *frame.b, oy + *frame.c + *frame.d);
+
+        painter.add(Shape::convex_polygon(
+            vec![origin, first, far, second],
+            color.gamma_multiply(0.2),
+            Stroke::new(1.5, color),
+        ));
+        painter.line_segment([origin, first], Stroke::new(3.0, color));
+
+        // Translation moves the whole parallelogram, so it is grabbed by its middle.
+        if frame.shift.is_some() {
+            let center = origin + (far - origin) / 2.0;
+            let response = handle(ui, id.with("translate"), center, color, HandleShape::Square);
+            if response.dragged() {
+                let delta = response.drag_delta() / mapping.pixels_per_unit;
+                if let Some((x, y)) = frame.shift.as_mut() {
+                    **x += delta.x;
+                    **y -= delta.y;
+                }
+                changed = true;
+            }
+        }
+
+        let response = handle(ui, id.with("first"), first, color, HandleShape::Circle);
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.dragged()) {
+            let (x, y) = mapping.to_world(pos);
+            *frame.a = x - ox;
+            *frame.c = y - oy;
+            changed = true;
+        }
+
+        let response = handle(ui, id.with("second"), second, color, HandleShape::Circle);
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.dragged()) {
+            let (x, y) = mapping.to_world(pos);
+            *frame.b = x - ox;
+            *frame.d = y - oy;
+            changed = true;
+        }
+
+        // Rotating about the origin corner keeps the shape and only turns it.
+        let response = handle(ui, id.with("rotate"), far, color, HandleShape::Ring);
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.dragged()) {
+            let before = (pos - response.drag_delta()) - origin;
+            let after = pos - origin;
+            let angle = after.y.atan2(after.x) - before.y.atan2(before.x);
+            // Screen y points down, so a clockwise turn on screen is a negative world angle.
+            let (sin, cos) = (-angle).sin_cos();
+            let (a, b, c, d) = (*frame.a, *frame.b, *frame.c, *frame.d);
+            *frame.a = cos * a - sin * c;
+            *frame.c = sin * a + cos * c;
+            *frame.b = cos * b - sin * d;
+            *frame.d = sin * b + cos * d;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Draws the orbit trap with a handle to move it, plus one to turn a line or resize a
+/// circle. Returns whether the trap changed.
+pub fn trap_overlay(ui: &mut Ui, mapping: CanvasMapping, trap: &mut OrbitTrap) -> bool {
+    let mut changed = false;
+    let painter = ui.painter_at(mapping.rect);
+    let color = Color32::from_rgb(240, 240, 240);
+    let stroke = Stroke::new(1.5, color);
+    let id = Id::new("trap_overlay");
+    let center = mapping.to_screen(trap.x, trap.y);
+    let (sin, cos) = trap.angle.sin_cos();
+
+    let edge = match trap.shape {
+        TrapShape::Point => None,
+        TrapShape::Line => {
+            // Long enough to cross any canvas that fits on screen.
+            let reach = 4.0 * mapping.rect.size().max_elem();
+            let direction = Vec2::new(cos, -sin);
+            painter.line_segment([center - direction * reach, center + direction * reach], stroke);
+            Some(mapping.to_screen(trap.x + 0.25 * cos, trap.y + 0.25 * sin))
+        }
+        TrapShape::Circle => {
+            painter.circle_stroke(center, trap.radius * mapping.pixels_per_unit, stroke);
+            Some(mapping.to_screen(trap.x + trap.radius, trap.y))
+        }
+    };
+
+    let response = handle(ui, id.with("center"), center, color, HandleShape::Square);
+    if response.dragged() {
+        let delta = response.drag_delta() / mapping.pixels_per_unit;
+        trap.x += delta.x;
+        trap.y -= delta.y;
+        changed = true;
+    }
+
+    if let Some(edge) = edge {
+        let response = handle(ui, id.with("edge"), edge, color, HandleShape::Circle);
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.dragged()) {
+            let (x, y) = mapping.to_world(pos);
+            let (dx, dy) = (x - trap.x, y - trap.y);
+            match trap.shape {
+                TrapShape::Line => trap.angle = dy.atan2(dx).rem_euclid(std::f32::consts::PI),
+                _ => trap.radius = dx.hypot(dy),
+            }
+            changed = true;
+        }
+    }
+    changed
+}
+
+enum HandleShape {
+    Square,
+    Circle,
+    Ring,
+}
+
+fn handle(ui: &mut Ui, id: Id, center: Pos2, color: Color32, shape: HandleShape) -> egui::Response {
+    let rect = Rect::from_center_size(center, Vec2::splat(HANDLE_SIZE));
+    let response = ui.interact(rect, id, Sense::drag());
+    let stroke = if response.hovered() || response.dragged() {
+        Stroke::new(2.0, Color32::WHITE)
+    } else {
+        Stroke::new(1.0, Color32::BLACK)
+    };
+    let radius = HANDLE_SIZE / 2.0;
+    match shape {
+        HandleShape::Square => {
+            ui.painter().rect(rect, 0.0, color, stroke);
+        }
+        HandleShape::Circle => {
+            ui.painter().circle(center, radius, color, stroke);
+        }
+        HandleShape::Ring => {
+            ui.painter().circle_stroke(center, radius, Stroke::new(2.0, color));
+            ui.painter().circle_stroke(center, radius + 1.0, stroke);
+        }
+    }
+    response
+}
@@ -0,0 +1,33 @@
+use barnsley::config::{EvaluationSettings, ImageSettings};
+use serde::{Deserialize, Serialize};
+
+use crate::coloring::Coloring;
+use crate::julia::EscapeTime;
+use crate::symmetry::Symmetry;
+use crate::transform::{Transform, TransformInfo};
+use crate::transitions::TransitionMatrix;
+
+/// Contents of a parameter file: the fields of barnsley's `Config`, with transforms of any
+/// kind the editor supports, plus what the editor keeps on top of it. Every extra field is
+/// optional, so plain barnsley configs load as well.
+#[derive(Serialize, Deserialize)]
+pub struct Parameters {
+    pub image_settings: ImageSettings,
+    pub evaluation_settings: EvaluationSettings,
+    pub transforms: Vec<Transform>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_transform: Option<Transform>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform_info: Vec<TransformInfo>,
+    #[serde(default)]
+    pub symmetry: Symmetry,
+    #[serde(default)]
+    pub transitions: TransitionMatrix,
+    #[serde(default)]
+    pub coloring: Coloring,
+    #[serde(default)]
+    pub escape_time: EscapeTime,
+    /// Seed for the chaos game, so that reopening a file gives the same image.
+    #[serde(default)]
+    pub seed: u64,
+}
@@ -0,0 +1,45 @@
+use egui::Ui;
+
+/// How much cheaper renders are while a slider or handle is being dragged. Once the drag
+/// ends the image is rendered again at full quality.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreviewQuality {
+    pub enabled: bool,
+    /// Fraction of the width and height to render at.
+    pub resolution: f32,
+    /// Fraction of the points to start.
+    pub samples: f32,
+}
+
+impl Default for PreviewQuality {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            resolution: 0.25,
+            samples: 0.1,
+        }
+    }
+}
+
+impl PreviewQuality {
+    /// A side of `size` pixels scaled down, keeping at least a few pixels.
+    pub fn side(&self, size: usize) -> usize {
+        ((size as f32 * self.resolution).round() as usize).max(16).min(size)
+    }
+
+    pub fn points(&self, num_points: usize) -> usize {
+        ((num_points as f32 * self.samples).round() as usize).max(1)
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.enabled, "Fast preview while dragging");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.add(egui::Slider::new(&mut self.resolution, 0.1..=1.0).text("Preview resolution"));
+            ui.add(
+                egui::Slider::new(&mut self.samples, 0.01..=1.0)
+                    .logarithmic(true)
+                    .text("Preview points"),
+            );
+        });
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+/// Slider ranges used when nothing else has been configured.
+const DEFAULT_RANGES: &[(&str, &str, f32, f32)] = &[
+    ("Linear", "a", -1.0, 1.0),
+    ("Linear", "b", -1.0, 1.0),
+    ("Linear", "c", -1.0, 1.0),
+    ("Linear", "d", -1.0, 1.0),
+    ("Linear", "Weight", 0.0, 10.0),
+    ("Affine", "a", -1.0, 1.0),
+    ("Affine", "b", -1.0, 1.0),
+    ("Affine", "c", -1.0, 1.0),
+    ("Affine", "d", -1.0, 1.0),
+    ("Affine", "xshift", -2.0, 2.0),
+    ("Affine", "yshift", -2.0, 2.0),
+    ("Affine", "Weight", 0.0, 10.0),
+    ("Geometric", "rotation", -PI, PI),
+    ("Geometric", "x scale", 0.0, 1.5),
+    ("Geometric", "y scale", -1.5, 1.5),
+    ("Geometric", "shear", -2.0, 2.0),
+    ("Moebius", "a.re", -1.0, 1.0),
+    ("Moebius", "a.im", -1.0, 1.0),
+    ("Moebius", "b.re", -1.0, 1.0),
+    ("Moebius", "b.im", -1.0, 1.0),
+    ("Moebius", "c.re", -1.0, 1.0),
+    ("Moebius", "c.im", -1.0, 1.0),
+    ("Moebius", "d.re", -1.0, 1.0),
+    ("Moebius", "d.im", -1.0, 1.0),
+    ("Moebius", "Weight", 0.0, 10.0),
+    ("InverseJulia", "r", 0.0, 3.0),
+    ("InverseJulia", "theta", 0.0, 2.0 * PI),
+    ("InverseJulia", "Weight", 0.0, 10.0),
+    ("Popcorn", "c", -1.0, 1.0),
+    ("Popcorn", "f", -1.0, 1.0),
+    ("Rings", "c", 0.0, 1.5),
+    ("Fan", "c", 0.0, 1.5),
+    ("Fan", "f", -PI, PI),
+    ("Blob", "high", 0.0, 2.0),
+    ("Blob", "low", 0.0, 2.0),
+    ("Blob", "waves", 0.0, 12.0),
+    ("PDJ", "a", -PI, PI),
+    ("PDJ", "b", -PI, PI),
+    ("PDJ", "c", -PI, PI),
+    ("PDJ", "d", -PI, PI),
+    ("Perspective", "angle", 0.0, PI),
+    ("Perspective", "distance", 0.0, 5.0),
+    ("Variation", "Weight", 0.0, 10.0),
+    ("Composite", "variation weight", -1.5, 1.5),
+    ("Composite", "Weight", 0.0, 10.0),
+    ("Expression", "Weight", 0.0, 10.0),
+];
+
+/// Slider range for one transform parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParameterRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Slider ranges for every transform parameter, keyed by transform kind and parameter name.
+/// Saved with the app's settings rather than with parameter files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParameterRanges {
+    ranges: BTreeMap<(String, String), ParameterRange>,
+}
+
+impl Default for ParameterRanges {
+    fn default() -> Self {
+        let ranges = DEFAULT_RANGES
+            .iter()
+            .map(|&(kind, name, min, max)| {
+                ((kind.to_owned(), name.to_owned()), ParameterRange { min, max })
+            })
+            .collect();
+        Self { ranges }
+    }
+}
+
+impl ParameterRanges {
+    /// Ranges saved by an earlier run laid over the defaults, so parameters added since
+    /// then still get a sensible range.
+    pub fn restore(saved: ParameterRanges) -> Self {
+        let mut ranges = Self::default();
+        ranges.ranges.extend(saved.ranges);
+        ranges
+    }
+
+    /// Parameters that have no configured range fall back to `-1.0..=1.0`.
+    pub fn get(&self, kind: &str, name: &str) -> RangeInclusive<f32> {
+        self.ranges
+            .get(&(kind.to_owned(), name.to_owned()))
+            .map_or(-1.0..=1.0, |range| range.min..=range.max)
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        egui::Grid::new("parameter_ranges")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for ((kind, name), range) in self.ranges.iter_mut() {
+                    ui.label(format!("{kind} {name}"));
+                    ui.add(egui::DragValue::new(&mut range.min).speed(0.01).prefix("min "));
+                    ui.add(egui::DragValue::new(&mut range.max).speed(0.01).prefix("max "));
+                    ui.end_row();
+                }
+            });
+        if ui.button("Reset ranges").clicked() {
+            *self = Self::default();
+        }
+    }
+}
@@ -0,0 +1,547 @@
+use barnsley::util::{Color, Point};
+use ndarray::{Array2, Array3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::coloring::Coloring;
+use crate::ifs::{AnimationSequence, IFS};
+use crate::symmetry::Symmetry;
+use crate::transform::{base_color, interpolate_transform, weight};
+use crate::transitions::TransitionMatrix;
+
+/// Per-transform switches shared by every system in a sequence, indexed like `IFS::transforms`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransformFlags {
+    /// Leaves the transform out of the chaos game entirely.
+    pub muted: bool,
+    /// When any transform is soloed, only points produced by soloed transforms are plotted.
+    pub soloed: bool,
+}
+
+/// Settings that change how a system is rendered without being part of the system itself.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Indexed like `IFS::transforms`.
+    pub flags: Vec<TransformFlags>,
+    pub symmetry: Symmetry,
+    pub transitions: TransitionMatrix,
+    pub coloring: Coloring,
+    /// Threads to split the walkers across; only native builds use more than one.
+    pub threads: usize,
+    /// Seed for the chaos game, so the same settings always give the same image.
+    pub seed: u64,
+}
+
+impl RenderOptions {
+    fn flag(&self, index: usize) -> TransformFlags {
+        self.flags.get(index).copied().unwrap_or_default()
+    }
+}
+
+/// Raw output of the chaos game before any tone mapping is applied.
+pub struct Accumulator {
+    pub width: usize,
+    pub height: usize,
+    /// Summed color of every point that landed in a pixel, indexed `[row, column, channel]`.
+    pub radiance: Array3<f32>,
+    /// Number of points that landed in a pixel, indexed `[row, column]`.
+    pub hits: Array2<f32>,
+    /// Number of points that were not plotted because a coordinate was infinite or NaN.
+    pub non_finite: usize,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            radiance: Array3::zeros((height, width, 3)),
+            hits: Array2::zeros((height, width)),
+            non_finite: 0,
+        }
+    }
+
+    /// Adds a point, ignoring anything outside the canvas. The shorter side of the
+    /// canvas spans `[-1, 1]` and the longer one extends further so pixels stay square.
+    fn add_point(&mut self, point: Point, color: Color) {
+        if !point.x.is_finite() || !point.y.is_finite() {
+            self.non_finite += 1;
+            return;
+        }
+        let pixels_per_unit = self.width.min(self.height) as f32 / 2.0;
+        let col = (self.width as f32 / 2.0 + point.x * pixels_per_unit).floor();
+        let row = (self.height as f32 / 2.0 - point.y * pixels_per_unit).floor();
+        if col < 0.0 || row < 0.0 || col >= self.width as f32 || row >= self.height as f32 {
+            return;
+        }
+        let (row, col) = (row as usize, col as usize);
+        self.radiance[[row, col, 0]] += color.r;
+        self.radiance[[row, col, 1]] += color.g;
+        self.radiance[[row, col, 2]] += color.b;
+        self.hits[[row, col]] += 1.0;
+    }
+
+    /// Adds another buffer of the same size, as rendered by another thread.
+    fn merge(&mut self, other: Accumulator) {
+        self.radiance += &other.radiance;
+        self.hits += &other.hits;
+        self.non_finite += other.non_finite;
+    }
+
+    /// Linear radiance divided by `scale`, without clipping.
+    pub fn linear(&self, scale: usize) -> Array3<f32> {
+        &self.radiance / scale as f32
+    }
+
+    /// Coverage of each pixel derived from its density, in `[0, 1]`.
+    pub fn alpha(&self, scale: usize) -> Array2<f32> {
+        self.hits.mapv(|hits| (hits / scale as f32).min(1.0))
+    }
+
+    /// Clips the linear radiance to `[0, 1]` and lays it over `background`, giving
+    /// straight RGBA indexed `[row, column, channel]`. Without a background the
+    /// alpha comes from the density instead and the color is divided back out.
+    pub fn composite(&self, scale: usize, background: Option<Color>) -> Array3<f32> {
+        let linear = self.linear(scale);
+        let alpha = self.alpha(scale);
+        Array3::from_shape_fn((self.height, self.width, 4), |(row, col, channel)| {
+            let a = alpha[[row, col]];
+            match (channel, background) {
+                (3, Some(_)) => 1.0,
+                (3, None) => a,
+                (_, Some(background)) => {
+                    let background = [background.r, background.g, background.b][channel];
+                    linear[[row, col, channel]].min(1.0) + (1.0 - a) * background
+                }
+                (_, None) if a > 0.0 => linear[[row, col, channel]].min(1.0) / a,
+                (_, None) => 0.0,
+            }
+            .clamp(0.0, 1.0)
+        })
+    }
+
+    /// Quantizes [`Accumulator::composite`] for display.
+    pub fn to_u8(&self, scale: usize, background: Option<Color>) -> Array3<u8> {
+        self.composite(scale, background)
+            .mapv(|value| (value * 255.0).round() as u8)
+    }
+}
+
+/// A transform followed by one element of the symmetry group, as picked by the chaos game.
+struct Map {
+    /// Index of the seed transform in `IFS::transforms`.
+    index: usize,
+    matrix: [f32; 4],
+    color: Color,
+}
+
+/// A system prepared for the chaos game: every transform followed by each symmetry element
+/// in turn, each copy with the seed's weight, and the transition weights between them.
+pub struct ChaosGame<'a> {
+    pub ifs: &'a IFS,
+    options: &'a RenderOptions,
+    maps: Vec<Map>,
+    weights: Vec<f32>,
+    total_weight: f32,
+    /// The weights to pick the next map with, for each map that may have been applied last.
+    transitions: Option<Vec<(Vec<f32>, f32)>>,
+}
+
+impl<'a> ChaosGame<'a> {
+    /// Returns `None` when no transform has any weight, so there is nothing to run.
+    pub fn new(ifs: &'a IFS, options: &'a RenderOptions) -> Option<Self> {
+        let elements = options.symmetry.elements();
+        let mut maps = Vec::new();
+        let mut weights = Vec::new();
+        for (index, transform) in ifs.transforms.iter().enumerate() {
+            let weight = if options.flag(index).muted { 0.0 } else { weight(transform).max(0.0) };
+            for (copy, &matrix) in elements.iter().enumerate() {
+                maps.push(Map {
+                    index,
+                    matrix,
+                    color: options.symmetry.shift_color(base_color(transform), copy),
+                });
+                weights.push(weight);
+            }
+        }
+        let total_weight: f32 = weights.iter().sum();
+        if maps.is_empty() || total_weight <= 0.0 {
+            return None;
+        }
+        let transitions = options.transitions.enabled.then(|| {
+            maps.iter()
+                .map(|from| {
+                    let row: Vec<f32> = maps
+                        .iter()
+                        .zip(&weights)
+                        .map(|(to, weight)| weight * options.transitions.get(from.index, to.index).max(0.0))
+                        .collect();
+                    let total = row.iter().sum();
+                    (row, total)
+                })
+                .collect()
+        });
+        Some(Self {
+            ifs,
+            options,
+            maps,
+            weights,
+            total_weight,
+            transitions,
+        })
+    }
+
+    /// Picks the map a walk starts from, ignoring transitions.
+    pub fn start(&self, rng: &mut impl Rng) -> usize {
+        choose_transform(&self.weights, self.total_weight, rng)
+    }
+
+    /// Picks the map to apply after `previous`. A transform with no way out restarts the
+    /// walk from the ordinary weights.
+    pub fn next(&self, previous: usize, rng: &mut impl Rng) -> usize {
+        match self.transitions.as_ref().map(|rows| &rows[previous]) {
+            Some((row, total)) if *total > 0.0 => choose_transform(row, *total, rng),
+            _ => choose_transform(&self.weights, self.total_weight, rng),
+        }
+    }
+
+    /// Applies `map` to `point`, taking any random choice the transform makes from `rng`.
+    pub fn apply(&self, map: usize, point: Point, rng: &mut impl Rng) -> Point {
+        let map = &self.maps[map];
+        let seed = self.ifs.transforms[map.index].transform_point_with(point, rng);
+        let [a, b, c, d] = map.matrix;
+        Point {
+            x: a * seed.x + b * seed.y,
+            y: c * seed.x + d * seed.y,
+        }
+    }
+
+    /// The index in `IFS::transforms` of the transform `map` is a copy of.
+    pub fn seed(&self, map: usize) -> usize {
+        self.maps[map].index
+    }
+
+    /// The symmetry element applied after the seed transform, as `[a, b, c, d]`.
+    pub fn matrix(&self, map: usize) -> [f32; 4] {
+        self.maps[map].matrix
+    }
+
+    pub fn color(&self, map: usize) -> Color {
+        self.maps[map].color
+    }
+
+    /// Whether points produced by `map` are plotted, given which transforms are soloed.
+    pub fn plotted(&self, map: usize) -> bool {
+        let any_soloed = self.options.flags.iter().any(|flags| flags.soloed);
+        !any_soloed || self.options.flag(self.maps[map].index).soloed
+    }
+
+    /// Where a point of the attractor ends up on the canvas, after the final transform.
+    pub fn plot_position(&self, point: Point, rng: &mut impl Rng) -> Point {
+        match &self.ifs.final_transform {
+            Some(transform) => transform.transform_point_with(point, rng),
+            None => point,
+        }
+    }
+}
+
+/// One thread per core where that can be found out, otherwise one.
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// Walkers are seeded in blocks of this many, so which random numbers a walker sees does
+/// not depend on how many threads there are.
+const BLOCK: usize = 64;
+/// Blocks are dealt out to this many lanes, each adding up into a buffer of its own, and the
+/// lanes are added together in order. Threads take whole lanes, so the sums come out the same
+/// to the last bit whatever the thread count. More threads than lanes would sit idle.
+const LANES: usize = 16;
+
+/// Runs the chaos game for `ifs`, starting `num_points` walkers that each take `num_iterations`
+/// steps. Each block of walkers has its own generator seeded from `options.seed` and the
+/// block's index, and the lanes of blocks are shared out between `options.threads` threads.
+/// The result depends only on the settings and the seed.
+pub fn render(
+    ifs: &IFS,
+    options: &RenderOptions,
+    width: usize,
+    height: usize,
+    num_iterations: usize,
+    num_points: usize,
+) -> Accumulator {
+    let mut accumulator = Accumulator::new(width, height);
+    let Some(game) = ChaosGame::new(ifs, options) else {
+        return accumulator;
+    };
+    let blocks = (0..num_points).step_by(BLOCK).len();
+    let lanes = blocks.min(LANES);
+    let threads = if cfg!(target_arch = "wasm32") { 1 } else { options.threads.clamp(1, lanes.max(1)) };
+    let lane = |lane: usize| {
+        let mut accumulator = Accumulator::new(width, height);
+        for block in (lane..blocks).step_by(lanes) {
+            let points = BLOCK.min(num_points - block * BLOCK);
+            let mut rng = block_rng(options.seed, block);
+            walk(&game, &mut accumulator, num_iterations, points, &mut rng);
+        }
+        accumulator
+    };
+    // Run the lanes a thread's worth at a time, adding each batch up in lane order.
+    for first in (0..lanes).step_by(threads) {
+        let batch = first..(first + threads).min(lanes);
+        let results: Vec<Accumulator> = if threads == 1 {
+            batch.map(lane).collect()
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = batch.map(|index| scope.spawn(move || lane(index))).collect();
+                handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+            })
+        };
+        for result in results {
+            accumulator.merge(result);
+        }
+    }
+    accumulator
+}
+
+/// The generator for one block of walkers. The seed and block index make up the key, so
+/// neighbouring seeds do not share blocks.
+fn block_rng(seed: u64, block: usize) -> StdRng {
+    let mut key = [0; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..16].copy_from_slice(&(block as u64).to_le_bytes());
+    StdRng::from_seed(key)
+}
+
+/// Plays the chaos game with `num_points` walkers, adding their points to `accumulator`.
+fn walk(
+    game: &ChaosGame<'_>,
+    accumulator: &mut Accumulator,
+    num_iterations: usize,
+    num_points: usize,
+    rng: &mut impl Rng,
+) {
+    let coloring = &game.options.coloring;
+    let plotted: Vec<bool> = (0..game.maps.len()).map(|map| game.plotted(map)).collect();
+    // Where each transform falls on the gradient when coloring by the last transform.
+    let last = game.ifs.transforms.len().saturating_sub(1).max(1) as f32;
+    let spread: Vec<f32> = (0..game.ifs.transforms.len()).map(|index| index as f32 / last).collect();
+
+    for _ in 0..num_points {
+        let mut point = Point {
+            x: rng.gen_range(-1.0..=1.0),
+            y: rng.gen_range(-1.0..=1.0),
+        };
+        let mut previous = game.start(rng);
+        let mut orbit = coloring.start(game.plot_position(point, rng), game.color(previous));
+        for _ in 0..num_iterations {
+            previous = game.next(previous, rng);
+            point = game.apply(previous, point, rng);
+            let position = game.plot_position(point, rng);
+            let color = coloring.step(&mut orbit, position, spread[game.seed(previous)], game.color(previous));
+            if plotted[previous] {
+                accumulator.add_point(position, color);
+            }
+        }
+    }
+}
+
+/// Picks an index with probability proportional to its weight.
+fn choose_transform(weights: &[f32], total_weight: f32, rng: &mut impl Rng) -> usize {
+    let mut remaining = rng.gen_range(0.0..total_weight);
+    for (index, weight) in weights.iter().enumerate() {
+        remaining -= weight;
+        if remaining < 0.0 {
+            return index;
+        }
+    }
+    weights.iter().rposition(|&weight| weight > 0.0).unwrap()
+}
+
+/// Blends two systems transform by transform, `pct` running from `start` (0.0) to `end` (1.0).
+/// A final transform that only `start` has is kept unchanged.
+pub fn interpolate(start: &IFS, end: &IFS, pct: f32) -> IFS {
+    let mut ifs = IFS::new();
+    for (a, b) in start.transforms.iter().zip(end.transforms.iter()) {
+        ifs.add_transform(interpolate_transform(a, b, pct));
+    }
+    ifs.final_transform = match (&start.final_transform, &end.final_transform) {
+        (Some(a), Some(b)) => Some(interpolate_transform(a, b, pct)),
+        (a, _) => a.clone(),
+    };
+    ifs
+}
+
+/// The system shown at frame `step` of `sequence`, counting frames across all of its segments.
+/// Frames that land on a keyframe are that system exactly, unblended.
+pub fn system_at(sequence: &AnimationSequence, step: usize) -> IFS {
+    let mut remaining = step;
+    for (segment, &step_count) in sequence.step_counts.iter().enumerate() {
+        if remaining == 0 {
+            return sequence.ifs_vec[segment].clone();
+        }
+        if remaining < step_count {
+            let pct = remaining as f32 / step_count as f32;
+            return interpolate(
+                &sequence.ifs_vec[segment],
+                &sequence.ifs_vec[segment + 1],
+                pct,
+            );
+        }
+        remaining -= step_count;
+    }
+    sequence.ifs_vec.last().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use barnsley::transform::AffineTransform;
+    use ndarray::Array2;
+
+    use super::*;
+    use crate::coloring::ColoringMode;
+    use crate::transform::Transform;
+
+    const SIZE: usize = 96;
+    const POINTS: usize = 4;
+    const ITERATIONS: usize = 50_000;
+
+    /// A Sierpinski triangle whose maps are twisted by `twist`, so two of them make keyframes
+    /// that blend into something neither is.
+    fn sierpinski(twist: f32) -> Vec<AffineTransform> {
+        [(-0.5, -0.5), (0.5, -0.5), (0.0, 0.5)]
+            .iter()
+            .enumerate()
+            .map(|(index, &(xshift, yshift))| AffineTransform {
+                a: 0.5,
+                b: twist,
+                c: -twist,
+                d: 0.5,
+                xshift,
+                yshift,
+                base_color: Color { r: 0.2 + 0.3 * index as f32, g: 0.5, b: 0.8 },
+                weight: 1.0,
+            })
+            .collect()
+    }
+
+    fn system(maps: &[AffineTransform]) -> IFS {
+        let mut ifs = IFS::new();
+        for &map in maps {
+            ifs.add_transform(Transform::AffineTransform(map));
+        }
+        ifs
+    }
+
+    fn barnsley_system(maps: &[AffineTransform]) -> barnsley::ifs::IFS {
+        let mut ifs = barnsley::ifs::IFS::new();
+        for &map in maps {
+            ifs.add_transform(map.into());
+        }
+        ifs
+    }
+
+    /// The pixels hit when rendering `step` of the sequence with this crate.
+    fn ours(keyframes: &[Vec<AffineTransform>], step: usize) -> Array2<bool> {
+        let sequence = AnimationSequence {
+            ifs_vec: keyframes.iter().map(|maps| system(maps)).collect(),
+            step_counts: vec![2; keyframes.len() - 1],
+        };
+        let ifs = system_at(&sequence, step);
+        let accumulator = render(&ifs, &RenderOptions::default(), SIZE, SIZE, ITERATIONS, POINTS);
+        accumulator.hits.mapv(|hits| hits > 0.0)
+    }
+
+    /// The pixels hit when rendering `step` of the sequence with barnsley.
+    fn barnsleys(keyframes: &[Vec<AffineTransform>], step: usize) -> Array2<bool> {
+        let sequence = barnsley::animation::AnimationSequence {
+            ifs_vec: keyframes.iter().map(|maps| barnsley_system(maps)).collect(),
+            step_counts: vec![2; keyframes.len() - 1],
+        };
+        let image = sequence.animate_single_step(SIZE, SIZE, ITERATIONS, POINTS, step).to_u8(1);
+        Array2::from_shape_fn((SIZE, SIZE), |(row, col)| (0..3).any(|channel| image[[row, col, channel]] > 0))
+    }
+
+    /// Fraction of the pixels set in `a` with a pixel set in `b` at most one pixel away. The
+    /// walks are random, so the two renders only agree up to the odd stray pixel.
+    fn overlap(a: &Array2<bool>, b: &Array2<bool>) -> f32 {
+        let near = |row: usize, col: usize| {
+            (row.saturating_sub(1)..=(row + 1).min(SIZE - 1))
+                .any(|r| (col.saturating_sub(1)..=(col + 1).min(SIZE - 1)).any(|c| b[[r, c]]))
+        };
+        let set: Vec<(usize, usize)> = a.indexed_iter().filter(|(_, &set)| set).map(|(index, _)| index).collect();
+        assert!(!set.is_empty(), "nothing was rendered");
+        set.iter().filter(|&&(row, col)| near(row, col)).count() as f32 / set.len() as f32
+    }
+
+    fn assert_matches_barnsley(keyframes: &[Vec<AffineTransform>], step: usize) {
+        let (ours, barnsleys) = (ours(keyframes, step), barnsleys(keyframes, step));
+        let (forward, backward) = (overlap(&ours, &barnsleys), overlap(&barnsleys, &ours));
+        assert!(forward > 0.95 && backward > 0.95, "overlap {forward} and {backward} at step {step}");
+    }
+
+    #[test]
+    fn keyframes_render_like_barnsley() {
+        let keyframes = [sierpinski(0.0), sierpinski(0.2)];
+        assert_matches_barnsley(&keyframes, 0);
+        assert_matches_barnsley(&keyframes, 2);
+    }
+
+    #[test]
+    fn blended_frames_render_like_barnsley() {
+        assert_matches_barnsley(&[sierpinski(0.0), sierpinski(0.2)], 1);
+    }
+
+    #[test]
+    fn blending_halfway_averages_the_coefficients() {
+        let sequence = AnimationSequence {
+            ifs_vec: vec![system(&sierpinski(0.0)), system(&sierpinski(0.2))],
+            step_counts: vec![2],
+        };
+        assert_eq!(system_at(&sequence, 0), sequence.ifs_vec[0]);
+        assert_eq!(system_at(&sequence, 1), system(&sierpinski(0.1)));
+        assert_eq!(system_at(&sequence, 2), sequence.ifs_vec[1]);
+    }
+
+    /// The default document's system, whose inverse Julia transforms pick a square root at
+    /// random, colored by depth so the summed colors are not round numbers.
+    fn seeded(threads: usize, seed: u64) -> Accumulator {
+        let mut ifs = IFS::new();
+        let color = Color { r: 0.13, g: 0.5, b: 0.93 };
+        ifs.add_transform(Transform::LinearTransform(barnsley::transform::LinearTransform::new(0.08, 0.44, -0.65, 0.19, color, 0.94)));
+        ifs.add_transform(Transform::InverseJuliaTransform(barnsley::transform::InverseJuliaTransform::new(1.17, 2.96, color, 0.96)));
+        ifs.add_transform(Transform::InverseJuliaTransform(barnsley::transform::InverseJuliaTransform::new(1.1, 1.99, color, 1.05)));
+        let options = RenderOptions {
+            coloring: Coloring {
+                mode: ColoringMode::Depth,
+                ..Coloring::default()
+            },
+            threads,
+            seed,
+            ..RenderOptions::default()
+        };
+        // Not a whole number of blocks, and more blocks than lanes.
+        render(&ifs, &options, 64, 48, 200, 20 * BLOCK + 13)
+    }
+
+    fn assert_same(a: &Accumulator, b: &Accumulator) {
+        assert!(a.radiance == b.radiance, "radiance differs");
+        assert!(a.hits == b.hits, "hits differ");
+        assert_eq!(a.non_finite, b.non_finite);
+    }
+
+    #[test]
+    fn seeded_renders_repeat_exactly() {
+        assert_same(&seeded(1, 3), &seeded(1, 3));
+        assert!(seeded(1, 3).radiance != seeded(1, 4).radiance, "the seed should change the image");
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_image() {
+        let single = seeded(1, 3);
+        assert!(single.hits.sum() > 0.0, "nothing was rendered");
+        for threads in [2, 3, LANES, 64] {
+            assert_same(&single, &seeded(threads, 3));
+        }
+    }
+}
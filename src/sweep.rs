@@ -0,0 +1,438 @@
+use std::path::Path;
+
+use barnsley::util::Color;
+use egui::epaint::text::Fonts;
+use egui::{Color32, FontDefinitions, FontId, ImageData, Pos2, Rect, TextureHandle, TextureOptions, Ui};
+use image::{Rgba, RgbaImage};
+
+use crate::ifs::IFS;
+use crate::parameters::Parameters;
+use crate::render::{self, RenderOptions};
+use crate::transform::Transform;
+
+/// Height of each line of the label under a cell, in pixels.
+const LINE_HEIGHT: u32 = 16;
+const LABEL_SIZE: f32 = 13.0;
+/// Space around and between the cells, in pixels.
+const GAP: u32 = 8;
+
+/// One parameter being varied, from `start` to `end` inclusive in `steps` even steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepAxis {
+    /// Index in `IFS::transforms`.
+    pub transform: usize,
+    /// A name from [`Transform::parameters_mut`].
+    pub parameter: String,
+    pub start: f32,
+    pub end: f32,
+    pub steps: usize,
+}
+
+impl SweepAxis {
+    fn value(&self, step: usize) -> f32 {
+        if self.steps <= 1 {
+            return self.start;
+        }
+        self.start + (self.end - self.start) * step as f32 / (self.steps - 1) as f32
+    }
+
+    /// Parses `transform:parameter=start:end:steps`, as taken by the command line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let error = || format!("expected transform:parameter=start:end:steps, got `{text}`");
+        let (target, range) = text.split_once('=').ok_or_else(error)?;
+        let (transform, parameter) = target.split_once(':').ok_or_else(error)?;
+        let range: Vec<&str> = range.split(':').collect();
+        let [start, end, steps] = range[..] else {
+            return Err(error());
+        };
+        let steps: usize = steps.trim().parse().map_err(|_| error())?;
+        if steps == 0 {
+            return Err(format!("an axis needs at least one step, got `{text}`"));
+        }
+        Ok(Self {
+            transform: transform.trim().parse().map_err(|_| error())?,
+            parameter: parameter.trim().to_owned(),
+            start: start.trim().parse().map_err(|_| error())?,
+            end: end.trim().parse().map_err(|_| error())?,
+            steps,
+        })
+    }
+}
+
+/// A grid of renders with the first axis varying across columns and the optional second
+/// axis down rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    pub first: SweepAxis,
+    pub second: Option<SweepAxis>,
+    /// Side of each square render, in pixels.
+    pub cell_size: u32,
+}
+
+impl Sweep {
+    /// Checks that every axis names a parameter that exists.
+    fn validate(&self, ifs: &IFS) -> Result<(), String> {
+        for axis in std::iter::once(&self.first).chain(&self.second) {
+            let Some(transform) = ifs.transforms.get(axis.transform) else {
+                return Err(format!("there is no transform {}", axis.transform));
+            };
+            let mut transform = transform.clone();
+            if !transform.set_parameter(&axis.parameter, 0.0) {
+                return Err(format!(
+                    "transform {} has no parameter `{}`; it has {}",
+                    axis.transform,
+                    axis.parameter,
+                    parameter_names(&transform).join(", "),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders every combination of values and lays them out with labels on `background`.
+    pub fn contact_sheet(
+        &self,
+        ifs: &IFS,
+        options: &RenderOptions,
+        num_iterations: usize,
+        num_points: usize,
+        background: Color,
+    ) -> Result<RgbaImage, String> {
+        self.validate(ifs)?;
+        let columns = self.first.steps.max(1) as u32;
+        let rows = self.second.as_ref().map_or(1, |axis| axis.steps.max(1)) as u32;
+        let cell = self.cell_size;
+        let lines = 1 + self.second.is_some() as u32;
+        let (column_pitch, row_pitch) = (cell + GAP, cell + lines * LINE_HEIGHT + GAP);
+        let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let fill = Rgba([to_u8(background.r), to_u8(background.g), to_u8(background.b), 255]);
+        let mut sheet = RgbaImage::from_pixel(GAP + columns * column_pitch, GAP + rows * row_pitch, fill);
+        let scale = 1.max((num_points * num_iterations) / (cell * cell) as usize);
+
+        let mut labels = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let mut system = ifs.clone();
+                let mut label = Vec::new();
+                let steps = std::iter::once((&self.first, column)).chain(self.second.as_ref().map(|axis| (axis, row)));
+                for (axis, step) in steps {
+                    let value = axis.value(step as usize);
+                    system.transforms[axis.transform].set_parameter(&axis.parameter, value);
+                    label.push(format!("#{} {} = {:.3}", axis.transform, axis.parameter, value));
+                }
+                let accumulator = render::render(&system, options, cell as usize, cell as usize, num_iterations, num_points);
+                let pixels = accumulator.to_u8(scale, Some(background));
+                let (left, top) = (GAP + column * column_pitch, GAP + row * row_pitch);
+                for ((y, x, channel), &value) in pixels.indexed_iter() {
+                    sheet.get_pixel_mut(left + x as u32, top + y as u32).0[channel] = value;
+                }
+                labels.push((left, top + cell + 2, label.join("\n")));
+            }
+        }
+        let text = Rgba([255 - fill.0[0], 255 - fill.0[1], 255 - fill.0[2], 255]);
+        draw_labels(&mut sheet, &labels, cell, text);
+        Ok(sheet)
+    }
+}
+
+pub fn parameter_names(transform: &Transform) -> Vec<String> {
+    transform
+        .clone()
+        .parameters_mut()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Writes each `(left, top, text)` into `image` using egui's default font, cut off at `width`.
+fn draw_labels(image: &mut RgbaImage, labels: &[(u32, u32, String)], width: u32, color: Rgba<u8>) {
+    let fonts = Fonts::new(1.0, 4096, FontDefinitions::default());
+    let galleys: Vec<_> = labels
+        .iter()
+        .map(|(left, top, text)| {
+            let galley = fonts.layout_no_wrap(text.clone(), FontId::proportional(LABEL_SIZE), Color32::WHITE);
+            (*left, *top, galley)
+        })
+        .collect();
+    // A fresh atlas is handed out whole, with every glyph laid out above.
+    let Some(delta) = fonts.font_image_delta() else {
+        return;
+    };
+    let ImageData::Font(atlas) = &delta.image else {
+        return;
+    };
+    for (left, top, galley) in galleys {
+        for glyph in galley.rows.iter().flat_map(|row| &row.glyphs) {
+            let uv = glyph.uv_rect;
+            let corner = glyph.pos + uv.offset;
+            for v in uv.min[1]..uv.max[1] {
+                for u in uv.min[0]..uv.max[0] {
+                    let coverage = atlas.pixels[v as usize * atlas.size[0] + u as usize];
+                    let x = corner.x.round() as i64 + (u - uv.min[0]) as i64;
+                    let y = corner.y.round() as i64 + (v - uv.min[1]) as i64;
+                    let (x, y) = (left as i64 + x, top as i64 + y);
+                    let inside = x >= left as i64 && x < (left + width).min(image.width()) as i64;
+                    if coverage <= 0.0 || !inside || y < 0 || y >= image.height() as i64 {
+                        continue;
+                    }
+                    let pixel = image.get_pixel_mut(x as u32, y as u32);
+                    for channel in 0..3 {
+                        let blended = pixel.0[channel] as f32 * (1.0 - coverage) + color.0[channel] as f32 * coverage;
+                        pixel.0[channel] = blended.round() as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Headless entry point: `sweep <parameters.json> <output.png> <axis> [<axis>] [--cell <pixels>]`,
+/// each axis written `transform:parameter=start:end:steps`.
+pub fn cli(args: &[String]) -> Result<(), String> {
+    let usage = "usage: sweep <parameters.json> <output.png> <transform:parameter=start:end:steps> [<second axis>] [--cell <pixels>]";
+    let mut positional = Vec::new();
+    let mut cell_size = 256;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--cell" {
+            let value = args.next().ok_or(usage)?;
+            cell_size = value
+                .parse()
+                .ok()
+                .filter(|&size| size > 0)
+                .ok_or_else(|| format!("invalid cell size `{value}`\n{usage}"))?;
+        } else {
+            positional.push(arg);
+        }
+    }
+    let (input, output, axes) = match &positional[..] {
+        [input, output, axes @ ..] if (1..=2).contains(&axes.len()) => (input, output, axes),
+        _ => return Err(usage.to_owned()),
+    };
+    let sweep = Sweep {
+        first: SweepAxis::parse(axes[0])?,
+        second: axes.get(1).map(|axis| SweepAxis::parse(axis)).transpose()?,
+        cell_size,
+    };
+
+    let data = std::fs::read_to_string(input).map_err(|error| format!("{input}: {error}"))?;
+    let parameters: Parameters = serde_json::from_str(&data).map_err(|error| format!("{input}: {error}"))?;
+    let mut ifs = IFS::new();
+    for transform in parameters.transforms {
+        ifs.add_transform(transform);
+    }
+    ifs.final_transform = parameters.final_transform;
+    let options = RenderOptions {
+        flags: parameters.transform_info.iter().map(|info| info.flags).collect(),
+        symmetry: parameters.symmetry,
+        transitions: parameters.transitions,
+        coloring: parameters.coloring,
+        threads: render::default_threads(),
+        seed: parameters.seed,
+    };
+    let sheet = sweep.contact_sheet(
+        &ifs,
+        &options,
+        parameters.evaluation_settings.num_iterations as usize,
+        parameters.evaluation_settings.num_points as usize,
+        Color { r: 0.0, g: 0.0, b: 0.0 },
+    )?;
+    sheet.save(Path::new(output)).map_err(|error| format!("{output}: {error}"))
+}
+
+/// The sweep window's state.
+pub struct SweepTool {
+    sweep: Sweep,
+    use_second: bool,
+    sheet: Option<(RgbaImage, TextureHandle)>,
+    error: Option<String>,
+}
+
+impl Default for SweepTool {
+    fn default() -> Self {
+        let axis = SweepAxis {
+            transform: 0,
+            parameter: "Weight".to_owned(),
+            start: 0.0,
+            end: 1.0,
+            steps: 5,
+        };
+        Self {
+            sweep: Sweep {
+                first: axis.clone(),
+                second: Some(axis),
+                cell_size: 192,
+            },
+            use_second: false,
+            sheet: None,
+            error: None,
+        }
+    }
+}
+
+impl SweepTool {
+    /// `labels` names each transform of `ifs`.
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        ifs: &IFS,
+        labels: &[String],
+        options: &RenderOptions,
+        (num_iterations, num_points): (usize, usize),
+        background: Color,
+    ) {
+        axis_ui(ui, "first_axis", &mut self.sweep.first, ifs, labels);
+        ui.checkbox(&mut self.use_second, "Vary a second parameter down the rows");
+        if self.use_second {
+            if let Some(axis) = self.sweep.second.as_mut() {
+                axis_ui(ui, "second_axis", axis, ifs, labels);
+            }
+        }
+        ui.add(egui::Slider::new(&mut self.sweep.cell_size, 64..=512).text("Cell size"));
+
+        ui.horizontal(|ui| {
+            if ui.button("Render contact sheet").clicked() {
+                let mut sweep = self.sweep.clone();
+                if !self.use_second {
+                    sweep.second = None;
+                }
+                match sweep.contact_sheet(ifs, options, num_iterations, num_points, background) {
+                    Ok(sheet) => {
+                        let size = [sheet.width() as usize, sheet.height() as usize];
+                        let image = egui::ColorImage::from_rgba_unmultiplied(size, sheet.as_raw());
+                        let texture = ui.ctx().load_texture("contact_sheet", image, TextureOptions::LINEAR);
+                        self.sheet = Some((sheet, texture));
+                        self.error = None;
+                    }
+                    Err(error) => self.error = Some(error),
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some((sheet, _)) = &self.sheet {
+                if ui.button("Save contact sheet").clicked() {
+                    if let Some(path) = rfd::FileDialog::new().add_filter("png", &["png"]).save_file() {
+                        if let Err(error) = sheet.save(path) {
+                            self.error = Some(error.to_string());
+                        }
+                    }
+                }
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        if let Some((_, texture)) = &self.sheet {
+            let size = texture.size_vec2();
+            let fit = (ui.available_width() / size.x).min(1.0);
+            let (rect, _) = ui.allocate_exact_size(size * fit, egui::Sense::hover());
+            ui.painter().image(
+                texture.id(),
+                rect,
+                Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+                Color32::WHITE,
+            );
+        }
+    }
+}
+
+fn axis_ui(ui: &mut Ui, id: &str, axis: &mut SweepAxis, ifs: &IFS, labels: &[String]) {
+    ui.push_id(id, |ui| {
+        axis.transform = axis.transform.min(ifs.transforms.len().saturating_sub(1));
+        egui::ComboBox::from_label("Transform")
+            .selected_text(labels.get(axis.transform).map_or("", String::as_str))
+            .show_ui(ui, |ui| {
+                for (index, label) in labels.iter().enumerate() {
+                    ui.selectable_value(&mut axis.transform, index, label);
+                }
+            });
+        let names = ifs.transforms.get(axis.transform).map(parameter_names).unwrap_or_default();
+        if !names.contains(&axis.parameter) {
+            if let Some(first) = names.first() {
+                axis.parameter = first.clone();
+            }
+        }
+        egui::ComboBox::from_label("Parameter")
+            .selected_text(axis.parameter.as_str())
+            .show_ui(ui, |ui| {
+                for name in names {
+                    ui.selectable_value(&mut axis.parameter, name.clone(), name);
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("From");
+            ui.add(egui::DragValue::new(&mut axis.start).speed(0.01));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut axis.end).speed(0.01));
+        });
+        ui.add(egui::Slider::new(&mut axis.steps, 2..=12).text("Steps"));
+    });
+    ui.add_space(4.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_an_axis() {
+        let axis = SweepAxis::parse("1:theta=0:2.5:4").unwrap();
+        assert_eq!(
+            axis,
+            SweepAxis {
+                transform: 1,
+                parameter: "theta".to_owned(),
+                start: 0.0,
+                end: 2.5,
+                steps: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn trims_whitespace_and_allows_negative_ranges() {
+        let axis = SweepAxis::parse(" 0 : Weight = -1.5 : 2 : 3 ").unwrap();
+        assert_eq!(axis.transform, 0);
+        assert_eq!(axis.parameter, "Weight");
+        assert_eq!((axis.start, axis.end, axis.steps), (-1.5, 2.0, 3));
+    }
+
+    #[test]
+    fn rejects_malformed_axes() {
+        for text in [
+            "",
+            "0:Weight",
+            "Weight=0:1:2",
+            "0:Weight=0:1",
+            "0:Weight=0:1:2:3",
+            "x:Weight=0:1:2",
+            "0:Weight=a:1:2",
+            "0:Weight=0:1:2.5",
+            "-1:Weight=0:1:2",
+        ] {
+            assert!(SweepAxis::parse(text).is_err(), "`{text}` should not parse");
+        }
+    }
+
+    #[test]
+    fn rejects_zero_steps() {
+        assert!(SweepAxis::parse("0:Weight=0:1:0").is_err());
+    }
+
+    #[test]
+    fn values_span_the_range() {
+        let axis = SweepAxis::parse("0:Weight=1:3:3").unwrap();
+        assert_eq!([axis.value(0), axis.value(1), axis.value(2)], [1.0, 2.0, 3.0]);
+        let single = SweepAxis::parse("0:Weight=1:3:1").unwrap();
+        assert_eq!(single.value(0), 1.0);
+    }
+
+    #[test]
+    fn rejects_a_zero_cell_size() {
+        let args: Vec<String> = ["in.json", "out.png", "0:Weight=0:1:2", "--cell", "0"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let error = cli(&args).unwrap_err();
+        assert!(error.starts_with("invalid cell size `0`"), "{error}");
+    }
+}
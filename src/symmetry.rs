@@ -0,0 +1,80 @@
+use std::f32::consts::PI;
+
+use barnsley::util::Color;
+use egui::ecolor::Hsva;
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum SymmetryKind {
+    #[default]
+    None,
+    /// Rotations by multiples of `2π / order`.
+    Cyclic,
+    /// The cyclic rotations plus reflections across `order` evenly spaced mirror lines.
+    Dihedral,
+}
+
+impl SymmetryKind {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            SymmetryKind::None => "None",
+            SymmetryKind::Cyclic => "Cyclic",
+            SymmetryKind::Dihedral => "Dihedral",
+        }
+    }
+}
+
+/// Copies of every transform that are rendered alongside it, rotated or reflected about
+/// the origin. Only the original "seed" transforms are edited.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Symmetry {
+    pub kind: SymmetryKind,
+    pub order: usize,
+    /// Hue rotation, in turns, added for each successive copy.
+    pub color_shift: f32,
+}
+
+impl Default for Symmetry {
+    fn default() -> Self {
+        Self {
+            kind: SymmetryKind::None,
+            order: 4,
+            color_shift: 0.0,
+        }
+    }
+}
+
+impl Symmetry {
+    /// The group elements as row-major 2x2 matrices `[a, b, c, d]`, identity first.
+    pub fn elements(&self) -> Vec<[f32; 4]> {
+        let order = self.order.max(1);
+        let mut elements = vec![[1.0, 0.0, 0.0, 1.0]];
+        if self.kind == SymmetryKind::None {
+            return elements;
+        }
+        for k in 1..order {
+            let (sin, cos) = (2.0 * PI * k as f32 / order as f32).sin_cos();
+            elements.push([cos, -sin, sin, cos]);
+        }
+        if self.kind == SymmetryKind::Dihedral {
+            for k in 0..order {
+                // Reflection across the line through the origin at angle `π k / order`.
+                let (sin, cos) = (2.0 * PI * k as f32 / order as f32).sin_cos();
+                elements.push([cos, sin, sin, -cos]);
+            }
+        }
+        elements
+    }
+
+    /// The color of the `copy`th copy of a transform colored `color`.
+    pub fn shift_color(&self, color: Color, copy: usize) -> Color {
+        if copy == 0 || self.color_shift == 0.0 {
+            return color;
+        }
+        let mut hsva = Hsva::from_rgb([color.r, color.g, color.b]);
+        hsva.h = (hsva.h + self.color_shift * copy as f32).rem_euclid(1.0);
+        let [r, g, b] = hsva.to_rgb();
+        Color { r, g, b }
+    }
+}
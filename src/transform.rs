@@ -1,331 +1,1274 @@
-use std::f32::consts::PI;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
 
 use barnsley::{
-    transform::{AffineTransform, InverseJuliaTransform, LinearTransform, MoebiusTransform},
-    util::Color,
+    transform::{
+        AffineTransform, InverseJuliaTransform, LinearTransform, MoebiusTransform, Transformable,
+    },
+    util::{Color, Point},
 };
 use egui::{Color32, Rgba, Ui};
+use num_complex::Complex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
 
-pub trait Visualize {
-    fn ui(&mut self, ui: &mut Ui, label: String, show_delete: bool) -> (bool, bool);
+use crate::expression::{self, ExpressionMode, Program};
+use crate::mandelbrot;
+use crate::ranges::ParameterRanges;
+use crate::render::TransformFlags;
+use crate::variation::{Variation, MAX_PARAMETERS};
+
+/// Every kind of transform the editor can use: barnsley's own, plus the ones defined here.
+///
+/// The variants shared with barnsley's `Transform` keep its names, so parameter files
+/// written by either read the same.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Transform {
+    LinearTransform(LinearTransform),
+    AffineTransform(AffineTransform),
+    MoebiusTransform(MoebiusTransform),
+    InverseJuliaTransform(InverseJuliaTransform),
+    VariationTransform(VariationTransform),
+    CompositeTransform(CompositeTransform),
+    ExpressionTransform(ExpressionTransform),
 }
 
-impl Visualize for LinearTransform {
-    fn ui(&mut self, ui: &mut Ui, label: String, show_delete: bool) -> (bool, bool) {
-        let mut rerender = false;
-        let mut delete_triggered = false;
+impl From<barnsley::transform::Transform> for Transform {
+    fn from(transform: barnsley::transform::Transform) -> Self {
+        match transform {
+            barnsley::transform::Transform::LinearTransform(t) => Transform::LinearTransform(t),
+            barnsley::transform::Transform::AffineTransform(t) => Transform::AffineTransform(t),
+            barnsley::transform::Transform::MoebiusTransform(t) => Transform::MoebiusTransform(t),
+            barnsley::transform::Transform::InverseJuliaTransform(t) => {
+                Transform::InverseJuliaTransform(t)
+            }
+        }
+    }
+}
 
-        ui.collapsing(label.clone(), |ui| {
-            ui.horizontal(|ui| {
-                ui.label("a");
-                let result = ui.add(egui::Slider::new(&mut self.a, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+impl From<LinearTransform> for Transform {
+    fn from(transform: LinearTransform) -> Self {
+        Transform::LinearTransform(transform)
+    }
+}
 
-            ui.horizontal(|ui| {
-                ui.label("b");
-                let result = ui.add(egui::Slider::new(&mut self.b, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+impl From<InverseJuliaTransform> for Transform {
+    fn from(transform: InverseJuliaTransform) -> Self {
+        Transform::InverseJuliaTransform(transform)
+    }
+}
 
-            ui.horizontal(|ui| {
-                ui.label("c");
-                let result = ui.add(egui::Slider::new(&mut self.c, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+impl Transform {
+    /// One transform of each kind with default parameters, in the order they are offered
+    /// in the editor. Every variation counts as its own kind.
+    pub fn kinds() -> Vec<Transform> {
+        let mut kinds = vec![
+            Transform::LinearTransform(LinearTransform::default()),
+            Transform::AffineTransform(AffineTransform::default()),
+            Transform::MoebiusTransform(MoebiusTransform::default()),
+            Transform::InverseJuliaTransform(InverseJuliaTransform::default()),
+        ];
+        kinds.extend(
+            Variation::iter().map(|variation| Transform::VariationTransform(VariationTransform::new(variation))),
+        );
+        kinds.push(Transform::CompositeTransform(CompositeTransform::default()));
+        kinds.push(Transform::ExpressionTransform(ExpressionTransform::default()));
+        kinds
+    }
 
-            ui.horizontal(|ui| {
-                ui.label("d");
-                let result = ui.add(egui::Slider::new(&mut self.d, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
+    /// The short name used for slider ranges and labels.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Transform::LinearTransform(_) => "Linear",
+            Transform::AffineTransform(_) => "Affine",
+            Transform::MoebiusTransform(_) => "Moebius",
+            Transform::InverseJuliaTransform(_) => "InverseJulia",
+            Transform::VariationTransform(t) => t.variation.get_name(),
+            Transform::CompositeTransform(_) => "Composite",
+            Transform::ExpressionTransform(_) => "Expression",
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        match self {
+            Transform::VariationTransform(t) => format!("{} variation", t.variation.get_name()),
+            Transform::CompositeTransform(_) => "Composite".to_owned(),
+            Transform::ExpressionTransform(_) => "Expression".to_owned(),
+            _ => self.to_barnsley().unwrap().get_name(),
+        }
+    }
+
+    pub fn transform_point(&self, point: Point) -> Point {
+        match self {
+            Transform::LinearTransform(t) => t.transform_point(point),
+            Transform::AffineTransform(t) => t.transform_point(point),
+            Transform::MoebiusTransform(t) => t.transform_point(point),
+            Transform::InverseJuliaTransform(t) => t.transform_point(point),
+            Transform::VariationTransform(t) => t.variation.apply(point, &t.parameters),
+            Transform::CompositeTransform(t) => t.transform_point(point),
+            Transform::ExpressionTransform(t) => t.transform_point(point),
+        }
+    }
+
+    /// Like [`Transform::transform_point`], but any random choice comes from `rng` so that a
+    /// seeded walk can be repeated. Only the inverse Julia transform makes one: which of the two
+    /// square roots of `z - c` to take.
+    pub fn transform_point_with(&self, point: Point, rng: &mut impl Rng) -> Point {
+        match self {
+            Transform::InverseJuliaTransform(t) => {
+                let c = Complex::from_polar(t.r, t.theta);
+                let root = (Complex::new(point.x, point.y) - c).sqrt();
+                let root = if rng.gen() { root } else { -root };
+                Point { x: root.re, y: root.im }
+            }
+            _ => self.transform_point(point),
+        }
+    }
+
+    /// Every numeric parameter by name, using the slider names where there are sliders.
+    pub fn parameters_mut(&mut self) -> Vec<(String, &mut f32)> {
+        match self {
+            Transform::LinearTransform(t) => named(
+                &["a", "b", "c", "d", "Weight"],
+                vec![&mut t.a, &mut t.b, &mut t.c, &mut t.d, &mut t.weight],
+            ),
+            Transform::AffineTransform(t) => named(
+                &["a", "b", "c", "d", "xshift", "yshift", "Weight"],
+                vec![&mut t.a, &mut t.b, &mut t.c, &mut t.d, &mut t.xshift, &mut t.yshift, &mut t.weight],
+            ),
+            Transform::MoebiusTransform(t) => named(
+                &["a.re", "a.im", "b.re", "b.im", "c.re", "c.im", "d.re", "d.im", "Weight"],
+                vec![
+                    &mut t.a.re, &mut t.a.im, &mut t.b.re, &mut t.b.im,
+                    &mut t.c.re, &mut t.c.im, &mut t.d.re, &mut t.d.im, &mut t.weight,
+                ],
+            ),
+            Transform::InverseJuliaTransform(t) => {
+                named(&["r", "theta", "Weight"], vec![&mut t.r, &mut t.theta, &mut t.weight])
+            }
+            Transform::VariationTransform(t) => {
+                let mut parameters = named(t.variation.parameter_names(), t.parameters.iter_mut().collect());
+                parameters.push(("Weight".to_owned(), &mut t.weight));
+                parameters
+            }
+            Transform::CompositeTransform(t) => {
+                let pre = &mut t.pre;
+                let mut parameters = named(
+                    &["pre a", "pre b", "pre c", "pre d", "pre xshift", "pre yshift"],
+                    vec![&mut pre.a, &mut pre.b, &mut pre.c, &mut pre.d, &mut pre.xshift, &mut pre.yshift],
+                );
+                for (index, term) in t.variations.iter_mut().enumerate() {
+                    parameters.push((format!("{} {} weight", index, term.variation.get_name()), &mut term.weight));
                 }
-            });
+                parameters.push(("Weight".to_owned(), &mut t.weight));
+                parameters
+            }
+            Transform::ExpressionTransform(t) => {
+                let mut parameters: Vec<_> = t
+                    .definition
+                    .parameters
+                    .iter_mut()
+                    .map(|(name, value)| (name.clone(), value))
+                    .collect();
+                parameters.push(("Weight".to_owned(), &mut t.definition.weight));
+                parameters
+            }
+        }
+    }
 
-            ui.horizontal(|ui| {
-                ui.label("Color");
-                let mut this_color: Color32 =
-                    Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
-                let response = ui.color_edit_button_srgba(&mut this_color);
-                if response.changed() {
-                    self.base_color = Color {
-                        r: this_color.r() as f32 / 255.0,
-                        g: this_color.g() as f32 / 255.0,
-                        b: this_color.b() as f32 / 255.0,
-                    };
-                    rerender = true;
+    /// Sets the parameter called `name` in [`Transform::parameters_mut`], returning whether
+    /// there is one.
+    pub fn set_parameter(&mut self, name: &str, value: f32) -> bool {
+        let found = match self.parameters_mut().into_iter().find(|(parameter, _)| parameter == name) {
+            Some((_, slot)) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        };
+        if let Transform::ExpressionTransform(t) = self {
+            t.update_values();
+        }
+        found
+    }
+
+    /// The same transform as barnsley's type, if barnsley has the kind.
+    pub fn to_barnsley(&self) -> Option<barnsley::transform::Transform> {
+        match *self {
+            Transform::LinearTransform(t) => Some(t.into()),
+            Transform::AffineTransform(t) => Some(t.into()),
+            Transform::MoebiusTransform(t) => Some(t.into()),
+            Transform::InverseJuliaTransform(t) => Some(t.into()),
+            Transform::VariationTransform(_)
+            | Transform::CompositeTransform(_)
+            | Transform::ExpressionTransform(_) => None,
+        }
+    }
+}
+
+impl Visualize for Transform {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
+        match self {
+            Transform::LinearTransform(t) => t.ui(ui, ranges),
+            Transform::AffineTransform(t) => t.ui(ui, ranges),
+            Transform::MoebiusTransform(t) => t.ui(ui, ranges),
+            Transform::InverseJuliaTransform(t) => t.ui(ui, ranges),
+            Transform::VariationTransform(t) => t.ui(ui, ranges),
+            Transform::CompositeTransform(t) => t.ui(ui, ranges),
+            Transform::ExpressionTransform(t) => t.ui(ui, ranges),
+        }
+    }
+}
+
+/// A single nonlinear [`Variation`] used as a transform on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VariationTransform {
+    pub variation: Variation,
+    /// Only the first `variation.parameter_names().len()` are used.
+    #[serde(default)]
+    pub parameters: [f32; MAX_PARAMETERS],
+    pub base_color: Color,
+    pub weight: f32,
+}
+
+impl VariationTransform {
+    pub fn new(variation: Variation) -> Self {
+        Self {
+            variation,
+            parameters: variation.default_parameters(),
+            base_color: Color { r: 1.0, g: 1.0, b: 1.0 },
+            weight: 1.0,
+        }
+    }
+}
+
+/// `(x, y) -> (a x + b y + xshift, c x + d y + yshift)`, as a building block of other transforms.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Affine {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub xshift: f32,
+    pub yshift: f32,
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self {
+            a: 1.0,
+            b: 0.0,
+            c: 0.0,
+            d: 1.0,
+            xshift: 0.0,
+            yshift: 0.0,
+        }
+    }
+}
+
+impl Affine {
+    pub fn apply(&self, point: Point) -> Point {
+        Point {
+            x: self.a * point.x + self.b * point.y + self.xshift,
+            y: self.c * point.x + self.d * point.y + self.yshift,
+        }
+    }
+
+    fn lerp(start: &Affine, end: &Affine, pct: f32) -> Affine {
+        Affine {
+            a: lerp(start.a, end.a, pct),
+            b: lerp(start.b, end.b, pct),
+            c: lerp(start.c, end.c, pct),
+            d: lerp(start.d, end.d, pct),
+            xshift: lerp(start.xshift, end.xshift, pct),
+            yshift: lerp(start.yshift, end.yshift, pct),
+        }
+    }
+}
+
+/// One term of a [`CompositeTransform`]'s variation sum.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WeightedVariation {
+    pub variation: Variation,
+    pub weight: f32,
+    #[serde(default)]
+    pub parameters: [f32; MAX_PARAMETERS],
+}
+
+impl WeightedVariation {
+    pub fn new(variation: Variation, weight: f32) -> Self {
+        Self {
+            variation,
+            weight,
+            parameters: variation.default_parameters(),
+        }
+    }
+}
+
+/// A flame-style transform: `post(sum of weight * variation(pre(point)))`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompositeTransform {
+    pub pre: Affine,
+    pub variations: Vec<WeightedVariation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post: Option<Affine>,
+    pub base_color: Color,
+    pub weight: f32,
+}
+
+impl Default for CompositeTransform {
+    fn default() -> Self {
+        Self {
+            pre: Affine::default(),
+            variations: vec![WeightedVariation::new(Variation::Linear, 1.0)],
+            post: None,
+            base_color: Color { r: 1.0, g: 1.0, b: 1.0 },
+            weight: 1.0,
+        }
+    }
+}
+
+impl CompositeTransform {
+    pub fn transform_point(&self, point: Point) -> Point {
+        let point = self.pre.apply(point);
+        let mut sum = Point { x: 0.0, y: 0.0 };
+        for term in &self.variations {
+            let varied = term.variation.apply(point, &term.parameters);
+            sum.x += term.weight * varied.x;
+            sum.y += term.weight * varied.y;
+        }
+        match &self.post {
+            Some(post) => post.apply(sum),
+            None => sum,
+        }
+    }
+
+    /// Terms at the same position with the same variation are blended parameter by
+    /// parameter. Any other pair fades one out while the other fades in, which is exact
+    /// because the sum is linear in the weights. A missing post-affine counts as the identity.
+    fn interpolate(start: &CompositeTransform, end: &CompositeTransform, pct: f32) -> CompositeTransform {
+        let mut variations = Vec::new();
+        for index in 0..start.variations.len().max(end.variations.len()) {
+            match (start.variations.get(index), end.variations.get(index)) {
+                (Some(s), Some(e)) if s.variation == e.variation => {
+                    let mut parameters = s.parameters;
+                    for (parameter, end) in parameters.iter_mut().zip(e.parameters) {
+                        *parameter = lerp(*parameter, end, pct);
+                    }
+                    variations.push(WeightedVariation {
+                        variation: s.variation,
+                        weight: lerp(s.weight, e.weight, pct),
+                        parameters,
+                    });
                 }
-                ui.end_row();
-            });
+                (s, e) => {
+                    if let Some(s) = s {
+                        variations.push(WeightedVariation { weight: s.weight * (1.0 - pct), ..*s });
+                    }
+                    if let Some(e) = e {
+                        variations.push(WeightedVariation { weight: e.weight * pct, ..*e });
+                    }
+                }
+            }
+        }
+        let post = match (&start.post, &end.post) {
+            (None, None) => None,
+            (s, e) => Some(Affine::lerp(
+                &s.unwrap_or_default(),
+                &e.unwrap_or_default(),
+                pct,
+            )),
+        };
+        CompositeTransform {
+            pre: Affine::lerp(&start.pre, &end.pre, pct),
+            variations,
+            post,
+            base_color: lerp_color(start.base_color, end.base_color, pct),
+            weight: lerp(start.weight, end.weight, pct),
+        }
+    }
+}
 
-            ui.horizontal(|ui| {
-                ui.label("Weight");
-                let result = ui.add(egui::Slider::new(&mut self.weight, 0.0..=10.0));
-                if result.changed() {
-                    rerender = true;
+/// Pairs up parameter names with the values they name.
+fn named<'a>(names: &[&str], values: Vec<&'a mut f32>) -> Vec<(String, &'a mut f32)> {
+    names.iter().map(|name| name.to_string()).zip(values).collect()
+}
+
+pub trait Visualize {
+    /// Shows the editor for the parameters, returning whether any of them changed.
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool;
+}
+
+/// A slider over the configured range next to a field for typing exact values, which
+/// may go beyond the range. Holding Shift makes dragging the field ten times finer.
+fn parameter_ui(ui: &mut Ui, name: &str, value: &mut f32, range: RangeInclusive<f32>) -> bool {
+    let mut speed = (range.end() - range.start()).abs() / 200.0;
+    if ui.input(|input| input.modifiers.shift) {
+        speed /= 10.0;
+    }
+    ui.horizontal(|ui| {
+        ui.label(name);
+        let slider = ui.add(egui::Slider::new(value, range).show_value(false));
+        let field = ui.add(egui::DragValue::new(value).speed(speed).max_decimals(6));
+        slider.changed() || field.changed()
+    })
+    .inner
+}
+
+/// Editor state attached to a transform and shared by every system in the sequence.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransformInfo {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+    #[serde(skip)]
+    pub flags: TransformFlags,
+}
+
+impl TransformInfo {
+    /// The user's name for the transform if it has one, otherwise its kind and position.
+    pub fn label(&self, transform: &Transform, index: usize) -> String {
+        let kind = transform.kind_name();
+        if self.name.is_empty() {
+            format!("{kind}: {index}")
+        } else {
+            format!("{} ({kind})", self.name)
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.name);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Note");
+            ui.text_edit_multiline(&mut self.note);
+        });
+    }
+}
+
+/// What is saved of an [`ExpressionTransform`]: the formula text and parameter values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpressionDefinition {
+    pub mode: ExpressionMode,
+    /// Formulas for `x'` and `y'`, used in real mode.
+    pub x: String,
+    pub y: String,
+    /// Formula for `z'`, used in complex mode.
+    pub z: String,
+    /// Values by parameter name. Names the formulas stop using are kept so that
+    /// editing a formula back and forth does not lose them.
+    #[serde(default)]
+    pub parameters: BTreeMap<String, f32>,
+    pub base_color: Color,
+    pub weight: f32,
+}
+
+impl ExpressionDefinition {
+    fn same_formulas(&self, other: &ExpressionDefinition) -> bool {
+        self.mode == other.mode && self.x == other.x && self.y == other.y && self.z == other.z
+    }
+}
+
+/// A transform the user writes as a formula, compiled once whenever the text changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ExpressionDefinition", into = "ExpressionDefinition")]
+pub struct ExpressionTransform {
+    pub definition: ExpressionDefinition,
+    /// One program per formula of the current mode, or the first error.
+    programs: Result<Vec<Program>, String>,
+    parameter_names: Vec<String>,
+    /// `definition.parameters` in the order of `parameter_names`.
+    parameter_values: Vec<f32>,
+}
+
+impl Default for ExpressionTransform {
+    fn default() -> Self {
+        Self::new(ExpressionDefinition {
+            mode: ExpressionMode::Real,
+            x: "sin(y)".to_owned(),
+            y: "x * cos(x)".to_owned(),
+            z: "sqrt(z - (c + d * i))".to_owned(),
+            parameters: BTreeMap::new(),
+            base_color: Color { r: 1.0, g: 1.0, b: 1.0 },
+            weight: 1.0,
+        })
+    }
+}
+
+impl From<ExpressionDefinition> for ExpressionTransform {
+    fn from(definition: ExpressionDefinition) -> Self {
+        Self::new(definition)
+    }
+}
+
+impl From<ExpressionTransform> for ExpressionDefinition {
+    fn from(transform: ExpressionTransform) -> Self {
+        transform.definition
+    }
+}
+
+impl ExpressionTransform {
+    pub fn new(definition: ExpressionDefinition) -> Self {
+        let mut transform = Self {
+            definition,
+            programs: Ok(Vec::new()),
+            parameter_names: Vec::new(),
+            parameter_values: Vec::new(),
+        };
+        transform.compile();
+        transform
+    }
+
+    fn compile(&mut self) {
+        let mut names = Vec::new();
+        let sources: Vec<&str> = match self.definition.mode {
+            ExpressionMode::Real => vec![&self.definition.x, &self.definition.y],
+            ExpressionMode::Complex => vec![&self.definition.z],
+        };
+        self.programs = sources
+            .into_iter()
+            .map(|source| expression::compile(source, self.definition.mode, &mut names))
+            .collect();
+        for name in &names {
+            self.definition.parameters.entry(name.clone()).or_insert(0.0);
+        }
+        self.parameter_names = names;
+        self.update_values();
+    }
+
+    fn update_values(&mut self) {
+        self.parameter_values = self
+            .parameter_names
+            .iter()
+            .map(|name| self.definition.parameters[name])
+            .collect();
+    }
+
+    /// A formula that does not compile sends every point off the canvas.
+    pub fn transform_point(&self, point: Point) -> Point {
+        let Ok(programs) = &self.programs else {
+            return Point { x: f32::NAN, y: f32::NAN };
+        };
+        let values = &self.parameter_values;
+        match self.definition.mode {
+            ExpressionMode::Real => {
+                let variables = [
+                    point.x,
+                    point.y,
+                    point.x.hypot(point.y),
+                    point.y.atan2(point.x),
+                ];
+                Point {
+                    x: programs[0].evaluate(&variables, values),
+                    y: programs[1].evaluate(&variables, values),
                 }
-            });
+            }
+            ExpressionMode::Complex => {
+                let z = programs[0].evaluate(&[Complex::new(point.x, point.y)], values);
+                Point { x: z.re, y: z.im }
+            }
+        }
+    }
+}
+
+pub fn weight(transform: &Transform) -> f32 {
+    match transform {
+        Transform::LinearTransform(t) => t.weight,
+        Transform::AffineTransform(t) => t.weight,
+        Transform::MoebiusTransform(t) => t.weight,
+        Transform::InverseJuliaTransform(t) => t.weight,
+        Transform::VariationTransform(t) => t.weight,
+        Transform::CompositeTransform(t) => t.weight,
+        Transform::ExpressionTransform(t) => t.definition.weight,
+    }
+}
+
+pub fn base_color(transform: &Transform) -> Color {
+    match transform {
+        Transform::LinearTransform(t) => t.base_color,
+        Transform::AffineTransform(t) => t.base_color,
+        Transform::MoebiusTransform(t) => t.base_color,
+        Transform::InverseJuliaTransform(t) => t.base_color,
+        Transform::VariationTransform(t) => t.base_color,
+        Transform::CompositeTransform(t) => t.base_color,
+        Transform::ExpressionTransform(t) => t.definition.base_color,
+    }
+}
+
+fn lerp(start: f32, end: f32, pct: f32) -> f32 {
+    start + (end - start) * pct
+}
 
-            if show_delete {
-                if ui.button(format!("delete {}", label)).clicked() {
-                    delete_triggered = true;
+fn lerp_color(start: Color, end: Color, pct: f32) -> Color {
+    Color {
+        r: lerp(start.r, end.r, pct),
+        g: lerp(start.g, end.g, pct),
+        b: lerp(start.b, end.b, pct),
+    }
+}
+
+/// Blends the parameters of two transforms of the same kind. Transforms of
+/// different kinds cannot be blended, so the nearer of the two is returned.
+pub fn interpolate_transform(start: &Transform, end: &Transform, pct: f32) -> Transform {
+    match (start, end) {
+        (Transform::LinearTransform(s), Transform::LinearTransform(e)) => {
+            Transform::LinearTransform(LinearTransform {
+                a: lerp(s.a, e.a, pct),
+                b: lerp(s.b, e.b, pct),
+                c: lerp(s.c, e.c, pct),
+                d: lerp(s.d, e.d, pct),
+                base_color: lerp_color(s.base_color, e.base_color, pct),
+                weight: lerp(s.weight, e.weight, pct),
+            })
+        }
+        (Transform::AffineTransform(s), Transform::AffineTransform(e)) => {
+            Transform::AffineTransform(AffineTransform {
+                a: lerp(s.a, e.a, pct),
+                b: lerp(s.b, e.b, pct),
+                c: lerp(s.c, e.c, pct),
+                d: lerp(s.d, e.d, pct),
+                xshift: lerp(s.xshift, e.xshift, pct),
+                yshift: lerp(s.yshift, e.yshift, pct),
+                base_color: lerp_color(s.base_color, e.base_color, pct),
+                weight: lerp(s.weight, e.weight, pct),
+            })
+        }
+        (Transform::MoebiusTransform(s), Transform::MoebiusTransform(e)) => {
+            Transform::MoebiusTransform(MoebiusTransform {
+                a: s.a + (e.a - s.a) * pct,
+                b: s.b + (e.b - s.b) * pct,
+                c: s.c + (e.c - s.c) * pct,
+                d: s.d + (e.d - s.d) * pct,
+                base_color: lerp_color(s.base_color, e.base_color, pct),
+                weight: lerp(s.weight, e.weight, pct),
+            })
+        }
+        (Transform::InverseJuliaTransform(s), Transform::InverseJuliaTransform(e)) => {
+            Transform::InverseJuliaTransform(InverseJuliaTransform {
+                r: lerp(s.r, e.r, pct),
+                theta: lerp(s.theta, e.theta, pct),
+                base_color: lerp_color(s.base_color, e.base_color, pct),
+                weight: lerp(s.weight, e.weight, pct),
+            })
+        }
+        (Transform::VariationTransform(s), Transform::VariationTransform(e))
+            if s.variation == e.variation =>
+        {
+            let mut parameters = s.parameters;
+            for (parameter, end) in parameters.iter_mut().zip(e.parameters) {
+                *parameter = lerp(*parameter, end, pct);
+            }
+            Transform::VariationTransform(VariationTransform {
+                variation: s.variation,
+                parameters,
+                base_color: lerp_color(s.base_color, e.base_color, pct),
+                weight: lerp(s.weight, e.weight, pct),
+            })
+        }
+        (Transform::CompositeTransform(s), Transform::CompositeTransform(e)) => {
+            Transform::CompositeTransform(CompositeTransform::interpolate(s, e, pct))
+        }
+        (Transform::ExpressionTransform(s), Transform::ExpressionTransform(e))
+            if s.definition.same_formulas(&e.definition) =>
+        {
+            let mut definition = s.definition.clone();
+            for (name, value) in definition.parameters.iter_mut() {
+                if let Some(end) = e.definition.parameters.get(name) {
+                    *value = lerp(*value, *end, pct);
                 }
             }
+            definition.base_color = lerp_color(s.definition.base_color, e.definition.base_color, pct);
+            definition.weight = lerp(s.definition.weight, e.definition.weight, pct);
+            Transform::ExpressionTransform(ExpressionTransform::new(definition))
+        }
+        _ if pct < 0.5 => start.clone(),
+        _ => end.clone(),
+    }
+}
+
+/// A 2x2 matrix `[[a, b], [c, d]]` written as `rotation * diag(xscale, yscale) * [[1, shear], [0, 1]]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometric {
+    pub rotation: f32,
+    pub xscale: f32,
+    pub yscale: f32,
+    pub shear: f32,
+}
+
+impl Geometric {
+    /// Decomposes the matrix so that [`Geometric::compose`] gives it back. When the first
+    /// column is zero there is no shear, and the rotation turns the second column instead.
+    pub fn decompose(a: f32, b: f32, c: f32, d: f32) -> Self {
+        let xscale = a.hypot(c);
+        if xscale == 0.0 {
+            return Self {
+                rotation: (-b).atan2(d),
+                xscale: 0.0,
+                yscale: b.hypot(d),
+                shear: 0.0,
+            };
+        }
+        let rotation = c.atan2(a);
+        let (sin, cos) = rotation.sin_cos();
+        Self {
+            rotation,
+            xscale,
+            yscale: cos * d - sin * b,
+            shear: (cos * b + sin * d) / xscale,
+        }
+    }
+
+    /// Returns `(a, b, c, d)`.
+    pub fn compose(&self) -> (f32, f32, f32, f32) {
+        let (sin, cos) = self.rotation.sin_cos();
+        (
+            cos * self.xscale,
+            cos * self.xscale * self.shear - sin * self.yscale,
+            sin * self.xscale,
+            sin * self.xscale * self.shear + cos * self.yscale,
+        )
+    }
+}
+
+/// Switches between editing the raw matrix and its geometric decomposition, remembering
+/// the choice separately for each editor.
+fn geometric_view_toggle(ui: &mut Ui) -> bool {
+    let id = ui.id().with("geometric_view");
+    let mut geometric = ui.data_mut(|data| *data.get_temp_mut_or_default::<bool>(id));
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut geometric, false, "Matrix");
+        ui.selectable_value(&mut geometric, true, "Geometric");
+    });
+    ui.data_mut(|data| data.insert_temp(id, geometric));
+    geometric
+}
+
+fn geometric_ui(
+    ui: &mut Ui,
+    a: &mut f32,
+    b: &mut f32,
+    c: &mut f32,
+    d: &mut f32,
+    ranges: &ParameterRanges,
+) -> bool {
+    let mut geometric = Geometric::decompose(*a, *b, *c, *d);
+    let mut changed = false;
+
+    for (name, value) in [
+        ("rotation", &mut geometric.rotation),
+        ("x scale", &mut geometric.xscale),
+        ("y scale", &mut geometric.yscale),
+        ("shear", &mut geometric.shear),
+    ] {
+        changed |= parameter_ui(ui, name, value, ranges.get("Geometric", name));
+    }
+
+    if changed {
+        (*a, *b, *c, *d) = geometric.compose();
+    }
+    changed
+}
+
+impl Visualize for LinearTransform {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
+        let mut rerender = false;
+
+        if geometric_view_toggle(ui) {
+            if geometric_ui(ui, &mut self.a, &mut self.b, &mut self.c, &mut self.d, ranges) {
+                rerender = true;
+            }
+        } else {
+            if parameter_ui(ui, "a", &mut self.a, ranges.get("Linear", "a")) {
+                rerender = true;
+            }
+
+            if parameter_ui(ui, "b", &mut self.b, ranges.get("Linear", "b")) {
+                rerender = true;
+            }
+
+            if parameter_ui(ui, "c", &mut self.c, ranges.get("Linear", "c")) {
+                rerender = true;
+            }
+
+            if parameter_ui(ui, "d", &mut self.d, ranges.get("Linear", "d")) {
+                rerender = true;
+            }
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut this_color: Color32 =
+                Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
+            let response = ui.color_edit_button_srgba(&mut this_color);
+            if response.changed() {
+                self.base_color = Color {
+                    r: this_color.r() as f32 / 255.0,
+                    g: this_color.g() as f32 / 255.0,
+                    b: this_color.b() as f32 / 255.0,
+                };
+                rerender = true;
+            }
+            ui.end_row();
         });
-        (rerender, delete_triggered)
+
+        if parameter_ui(ui, "Weight", &mut self.weight, ranges.get("Linear", "Weight")) {
+            rerender = true;
+        }
+
+        rerender
     }
 }
 
 impl Visualize for AffineTransform {
-    fn ui(&mut self, ui: &mut Ui, label: String, show_delete: bool) -> (bool, bool) {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
         let mut rerender = false;
-        let mut delete_triggered = false;
 
-        ui.collapsing(label.clone(), |ui| {
-            ui.horizontal(|ui| {
-                ui.label("a");
-                let result = ui.add(egui::Slider::new(&mut self.a, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if geometric_view_toggle(ui) {
+            if geometric_ui(ui, &mut self.a, &mut self.b, &mut self.c, &mut self.d, ranges) {
+                rerender = true;
+            }
+        } else {
+            if parameter_ui(ui, "a", &mut self.a, ranges.get("Affine", "a")) {
+                rerender = true;
+            }
 
-            ui.horizontal(|ui| {
-                ui.label("b");
-                let result = ui.add(egui::Slider::new(&mut self.b, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+            if parameter_ui(ui, "b", &mut self.b, ranges.get("Affine", "b")) {
+                rerender = true;
+            }
 
-            ui.horizontal(|ui| {
-                ui.label("c");
-                let result = ui.add(egui::Slider::new(&mut self.c, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+            if parameter_ui(ui, "c", &mut self.c, ranges.get("Affine", "c")) {
+                rerender = true;
+            }
 
-            ui.horizontal(|ui| {
-                ui.label("d");
-                let result = ui.add(egui::Slider::new(&mut self.d, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+            if parameter_ui(ui, "d", &mut self.d, ranges.get("Affine", "d")) {
+                rerender = true;
+            }
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("xshift");
-                let result = ui.add(egui::Slider::new(&mut self.xshift, -2.0..=2.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "xshift", &mut self.xshift, ranges.get("Affine", "xshift")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("yshift");
-                let result = ui.add(egui::Slider::new(&mut self.yshift, -2.0..=2.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "yshift", &mut self.yshift, ranges.get("Affine", "yshift")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("Color");
-                let mut this_color: Color32 =
-                    Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
-                let response = ui.color_edit_button_srgba(&mut this_color);
-                if response.changed() {
-                    self.base_color = Color {
-                        r: this_color.r() as f32 / 255.0,
-                        g: this_color.g() as f32 / 255.0,
-                        b: this_color.b() as f32 / 255.0,
-                    };
-                    rerender = true;
-                }
-                ui.end_row();
-            });
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut this_color: Color32 =
+                Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
+            let response = ui.color_edit_button_srgba(&mut this_color);
+            if response.changed() {
+                self.base_color = Color {
+                    r: this_color.r() as f32 / 255.0,
+                    g: this_color.g() as f32 / 255.0,
+                    b: this_color.b() as f32 / 255.0,
+                };
+                rerender = true;
+            }
+            ui.end_row();
+        });
 
-            ui.horizontal(|ui| {
-                ui.label("Weight");
-                let result = ui.add(egui::Slider::new(&mut self.weight, 0.0..=10.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "Weight", &mut self.weight, ranges.get("Affine", "Weight")) {
+            rerender = true;
+        }
 
-            if show_delete {
-                if ui.button(format!("delete {}", label)).clicked() {
-                    delete_triggered = true;
-                }
-            }   
-        });
-        (rerender, delete_triggered)
+        rerender
     }
 }
 
 impl Visualize for MoebiusTransform {
-    fn ui(&mut self, ui: &mut Ui, label: String, show_delete: bool) -> (bool, bool) {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
         let mut rerender = false;
-        let mut delete_triggered = false;
 
-        ui.collapsing(label.clone(), |ui| {
-            ui.horizontal(|ui| {
-                ui.label("a.re");
-                let result = ui.add(egui::Slider::new(&mut self.a.re, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "a.re", &mut self.a.re, ranges.get("Moebius", "a.re")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("a.im");
-                let result = ui.add(egui::Slider::new(&mut self.a.im, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "a.im", &mut self.a.im, ranges.get("Moebius", "a.im")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("b.re");
-                let result = ui.add(egui::Slider::new(&mut self.b.re, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "b.re", &mut self.b.re, ranges.get("Moebius", "b.re")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("b.im");
-                let result = ui.add(egui::Slider::new(&mut self.b.im, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "b.im", &mut self.b.im, ranges.get("Moebius", "b.im")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("c.re");
-                let result = ui.add(egui::Slider::new(&mut self.c.re, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "c.re", &mut self.c.re, ranges.get("Moebius", "c.re")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("c.im");
-                let result = ui.add(egui::Slider::new(&mut self.c.im, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "c.im", &mut self.c.im, ranges.get("Moebius", "c.im")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("d.re");
-                let result = ui.add(egui::Slider::new(&mut self.d.re, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "d.re", &mut self.d.re, ranges.get("Moebius", "d.re")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("d.im");
-                let result = ui.add(egui::Slider::new(&mut self.d.im, -1.0..=1.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "d.im", &mut self.d.im, ranges.get("Moebius", "d.im")) {
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("Color");
-                let mut this_color: Color32 =
-                    Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
-                let response = ui.color_edit_button_srgba(&mut this_color);
-                if response.changed() {
-                    self.base_color = Color {
-                        r: this_color.r() as f32 / 255.0,
-                        g: this_color.g() as f32 / 255.0,
-                        b: this_color.b() as f32 / 255.0,
-                    };
-                    rerender = true;
-                }
-                ui.end_row();
-            });
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut this_color: Color32 =
+                Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
+            let response = ui.color_edit_button_srgba(&mut this_color);
+            if response.changed() {
+                self.base_color = Color {
+                    r: this_color.r() as f32 / 255.0,
+                    g: this_color.g() as f32 / 255.0,
+                    b: this_color.b() as f32 / 255.0,
+                };
+                rerender = true;
+            }
+            ui.end_row();
+        });
 
-            ui.horizontal(|ui| {
-                ui.label("Weight");
-                let result = ui.add(egui::Slider::new(&mut self.weight, 0.0..=10.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        if parameter_ui(ui, "Weight", &mut self.weight, ranges.get("Moebius", "Weight")) {
+            rerender = true;
+        }
 
-            if show_delete {
-                if ui.button(format!("delete {}", label)).clicked() {
-                    delete_triggered = true;
-                }
+        rerender
+    }
+}
+
+impl Visualize for InverseJuliaTransform {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
+        let mut rerender = false;
+
+        if parameter_ui(ui, "r", &mut self.r, ranges.get("InverseJulia", "r")) {
+            rerender = true;
+        }
+
+        if parameter_ui(ui, "theta", &mut self.theta, ranges.get("InverseJulia", "theta")) {
+            rerender = true;
+        }
+
+        ui.collapsing("Pick on the Mandelbrot set", |ui| {
+            if mandelbrot::picker(ui, &mut self.r, &mut self.theta) {
+                rerender = true;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut this_color: Color32 =
+                Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
+            let response = ui.color_edit_button_srgba(&mut this_color);
+            if response.changed() {
+                self.base_color = Color {
+                    r: this_color.r() as f32 / 255.0,
+                    g: this_color.g() as f32 / 255.0,
+                    b: this_color.b() as f32 / 255.0,
+                };
+                rerender = true;
             }
+            ui.end_row();
         });
-        (rerender, delete_triggered)
+
+        if parameter_ui(ui, "Weight", &mut self.weight, ranges.get("InverseJulia", "Weight")) {
+            rerender = true;
+        }
+
+        rerender
     }
 }
 
-impl Visualize for InverseJuliaTransform {
-    fn ui(&mut self, ui: &mut Ui, label: String, show_delete: bool) -> (bool, bool) {
+impl Visualize for VariationTransform {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
         let mut rerender = false;
-        let mut delete_triggered = false;
+        let kind = self.variation.get_name();
 
-        ui.collapsing(label.clone(), |ui| {
-            ui.horizontal(|ui| {
-                ui.label("r");
-                let result = ui.add(egui::Slider::new(&mut self.r, 0.0..=3.0));
-                if result.changed() {
-                    rerender = true;
-                }
-            });
+        for (name, value) in self.variation.parameter_names().iter().zip(self.parameters.iter_mut()) {
+            if parameter_ui(ui, name, value, ranges.get(kind, name)) {
+                rerender = true;
+            }
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("theta");
-                let result = ui.add(egui::Slider::new(&mut self.theta, 0.0..=2.0 * PI));
-                if result.changed() {
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut this_color: Color32 =
+                Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
+            let response = ui.color_edit_button_srgba(&mut this_color);
+            if response.changed() {
+                self.base_color = Color {
+                    r: this_color.r() as f32 / 255.0,
+                    g: this_color.g() as f32 / 255.0,
+                    b: this_color.b() as f32 / 255.0,
+                };
+                rerender = true;
+            }
+            ui.end_row();
+        });
+
+        if parameter_ui(ui, "Weight", &mut self.weight, ranges.get("Variation", "Weight")) {
+            rerender = true;
+        }
+
+        rerender
+    }
+}
+
+/// The pre- and post-affine editors, with the same matrix/geometric toggle as [`AffineTransform`].
+fn affine_ui(ui: &mut Ui, affine: &mut Affine, ranges: &ParameterRanges) -> bool {
+    let mut rerender = false;
+
+    if geometric_view_toggle(ui) {
+        if geometric_ui(ui, &mut affine.a, &mut affine.b, &mut affine.c, &mut affine.d, ranges) {
+            rerender = true;
+        }
+    } else {
+        for (name, value) in [
+            ("a", &mut affine.a),
+            ("b", &mut affine.b),
+            ("c", &mut affine.c),
+            ("d", &mut affine.d),
+        ] {
+            if parameter_ui(ui, name, value, ranges.get("Affine", name)) {
+                rerender = true;
+            }
+        }
+    }
+
+    if parameter_ui(ui, "xshift", &mut affine.xshift, ranges.get("Affine", "xshift")) {
+        rerender = true;
+    }
+
+    if parameter_ui(ui, "yshift", &mut affine.yshift, ranges.get("Affine", "yshift")) {
+        rerender = true;
+    }
+
+    rerender
+}
+
+impl Visualize for CompositeTransform {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
+        let mut rerender = false;
+
+        ui.label("Pre-affine");
+        ui.push_id("pre", |ui| {
+            if affine_ui(ui, &mut self.pre, ranges) {
+                rerender = true;
+            }
+        });
+
+        ui.separator();
+        ui.label("Variations");
+        let mut removed = None;
+        for (index, term) in self.variations.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong(term.variation.get_name());
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                if parameter_ui(ui, "weight", &mut term.weight, ranges.get("Composite", "variation weight")) {
                     rerender = true;
                 }
+                let kind = term.variation.get_name();
+                for (name, value) in term.variation.parameter_names().iter().zip(term.parameters.iter_mut()) {
+                    if parameter_ui(ui, name, value, ranges.get(kind, name)) {
+                        rerender = true;
+                    }
+                }
             });
+        }
+        if let Some(index) = removed {
+            self.variations.remove(index);
+            rerender = true;
+        }
 
-            ui.horizontal(|ui| {
-                ui.label("Color");
-                let mut this_color: Color32 =
-                    Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
-                let response = ui.color_edit_button_srgba(&mut this_color);
-                if response.changed() {
-                    self.base_color = Color {
-                        r: this_color.r() as f32 / 255.0,
-                        g: this_color.g() as f32 / 255.0,
-                        b: this_color.b() as f32 / 255.0,
-                    };
+        ui.horizontal(|ui| {
+            let id = ui.id().with("variation_to_add");
+            let mut selected = ui.data_mut(|data| *data.get_temp_mut_or_default::<Variation>(id));
+            egui::ComboBox::from_id_source(id)
+                .selected_text(selected.get_name())
+                .show_ui(ui, |ui| {
+                    for variation in Variation::iter() {
+                        ui.selectable_value(&mut selected, variation, variation.get_name());
+                    }
+                });
+            ui.data_mut(|data| data.insert_temp(id, selected));
+            if ui.button("Add variation").clicked() {
+                self.variations.push(WeightedVariation::new(selected, 1.0));
+                rerender = true;
+            }
+        });
+
+        ui.separator();
+        let mut has_post = self.post.is_some();
+        if ui.checkbox(&mut has_post, "Post-affine").changed() {
+            self.post = has_post.then(Affine::default);
+            rerender = true;
+        }
+        if let Some(post) = &mut self.post {
+            ui.push_id("post", |ui| {
+                if affine_ui(ui, post, ranges) {
                     rerender = true;
                 }
-                ui.end_row();
             });
+        }
 
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut this_color: Color32 =
+                Rgba::from_rgb(self.base_color.r, self.base_color.g, self.base_color.b).into();
+            let response = ui.color_edit_button_srgba(&mut this_color);
+            if response.changed() {
+                self.base_color = Color {
+                    r: this_color.r() as f32 / 255.0,
+                    g: this_color.g() as f32 / 255.0,
+                    b: this_color.b() as f32 / 255.0,
+                };
+                rerender = true;
+            }
+            ui.end_row();
+        });
+
+        if parameter_ui(ui, "Weight", &mut self.weight, ranges.get("Composite", "Weight")) {
+            rerender = true;
+        }
+
+        rerender
+    }
+}
+
+impl Visualize for ExpressionTransform {
+    fn ui(&mut self, ui: &mut Ui, ranges: &ParameterRanges) -> bool {
+        let mut rerender = false;
+        let mut recompile = false;
+
+        ui.horizontal(|ui| {
+            for mode in ExpressionMode::iter() {
+                if ui
+                    .selectable_value(&mut self.definition.mode, mode, mode.get_name())
+                    .changed()
+                {
+                    recompile = true;
+                }
+            }
+        });
+        let formulas = match self.definition.mode {
+            ExpressionMode::Real => vec![("x' =", &mut self.definition.x), ("y' =", &mut self.definition.y)],
+            ExpressionMode::Complex => vec![("z' =", &mut self.definition.z)],
+        };
+        for (label, formula) in formulas {
             ui.horizontal(|ui| {
-                ui.label("Weight");
-                let result = ui.add(egui::Slider::new(&mut self.weight, 0.0..=10.0));
-                if result.changed() {
-                    rerender = true;
+                ui.label(label);
+                if ui.add(egui::TextEdit::singleline(formula).code_editor()).changed() {
+                    recompile = true;
                 }
             });
+        }
+        if recompile {
+            self.compile();
+            rerender = true;
+        }
+        if let Err(error) = &self.programs {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        ui.label(match self.definition.mode {
+            ExpressionMode::Real => "Use x, y, r and theta. Any other name becomes a parameter.",
+            ExpressionMode::Complex => "Use z and i. Any other name becomes a real parameter, so write c + d * i for a complex one.",
+        });
 
-            if show_delete {
-                if ui.button(format!("delete {}", label)).clicked() {
-                    delete_triggered = true;
-                }
+        for name in &self.parameter_names {
+            let value = self.definition.parameters.get_mut(name).unwrap();
+            if parameter_ui(ui, name, value, ranges.get("Expression", name)) {
+                rerender = true;
             }
+        }
+        if rerender {
+            self.update_values();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut this_color: Color32 = Rgba::from_rgb(
+                self.definition.base_color.r,
+                self.definition.base_color.g,
+                self.definition.base_color.b,
+            )
+            .into();
+            let response = ui.color_edit_button_srgba(&mut this_color);
+            if response.changed() {
+                self.definition.base_color = Color {
+                    r: this_color.r() as f32 / 255.0,
+                    g: this_color.g() as f32 / 255.0,
+                    b: this_color.b() as f32 / 255.0,
+                };
+                rerender = true;
+            }
+            ui.end_row();
         });
-        (rerender, delete_triggered)
+
+        if parameter_ui(ui, "Weight", &mut self.definition.weight, ranges.get("Expression", "Weight")) {
+            rerender = true;
+        }
+
+        rerender
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trips(matrix: (f32, f32, f32, f32)) {
+        let (a, b, c, d) = matrix;
+        let composed = Geometric::decompose(a, b, c, d).compose();
+        let close = |x: f32, y: f32| (x - y).abs() < 1e-5;
+        assert!(
+            close(composed.0, a) && close(composed.1, b) && close(composed.2, c) && close(composed.3, d),
+            "{matrix:?} came back as {composed:?}"
+        );
+    }
+
+    #[test]
+    fn geometric_view_round_trips_ordinary_matrices() {
+        assert_round_trips((1.0, 0.0, 0.0, 1.0));
+        assert_round_trips((0.5, -0.3, 0.2, 0.7));
+        assert_round_trips((0.07927406, 0.4419875, -0.64647937, 0.19174504));
+        assert_round_trips((-0.4, 0.1, -0.6, -0.2));
+    }
+
+    #[test]
+    fn geometric_view_round_trips_reflections() {
+        assert_round_trips((1.0, 0.0, 0.0, -1.0));
+        assert_round_trips((-1.0, 0.0, 0.0, 1.0));
+        assert_round_trips((0.0, 1.0, 1.0, 0.0));
+        assert_round_trips((0.3, 0.6, 0.5, -0.2));
+    }
+
+    #[test]
+    fn geometric_view_round_trips_degenerate_matrices() {
+        assert_round_trips((0.0, 0.0, 0.0, 0.0));
+        assert_round_trips((0.5, 1.0, 0.25, 0.5));
+        assert_round_trips((0.0, 0.5, 0.0, 0.3));
+        assert_round_trips((0.0, -0.5, 0.0, 0.0));
+        assert_round_trips((0.4, 0.0, -0.3, 0.0));
+    }
+
+    #[test]
+    fn decomposes_into_the_expected_parts() {
+        let geometric = Geometric::decompose(0.0, -2.0, 2.0, 0.0);
+        assert!((geometric.rotation - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!((geometric.xscale - 2.0).abs() < 1e-6);
+        assert!((geometric.yscale - 2.0).abs() < 1e-6);
+        assert!(geometric.shear.abs() < 1e-6);
+        let reflected = Geometric::decompose(1.0, 0.0, 0.0, -1.0);
+        assert!(reflected.yscale < 0.0, "a reflection shows up as a negative y scale");
     }
 }
@@ -0,0 +1,98 @@
+use egui::Ui;
+use serde::{Deserialize, Serialize};
+
+/// Weights for which transform may follow which, turning the chaos game into a
+/// graph-directed (recurrent) IFS. Entry `[from][to]` scales the chance of applying
+/// transform `to` right after `from`, on top of `to`'s own weight, so a matrix of ones
+/// behaves exactly like the ordinary chaos game and a zero forbids the transition.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransitionMatrix {
+    pub enabled: bool,
+    #[serde(default)]
+    pub weights: Vec<Vec<f32>>,
+}
+
+impl TransitionMatrix {
+    /// Missing entries count as one, so the matrix may lag behind the transform list.
+    pub fn get(&self, from: usize, to: usize) -> f32 {
+        self.weights
+            .get(from)
+            .and_then(|row| row.get(to))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Grows or shrinks the matrix to `count` transforms, filling new entries with ones.
+    pub fn resize(&mut self, count: usize) {
+        self.weights.resize(count, Vec::new());
+        for row in self.weights.iter_mut() {
+            row.resize(count, 1.0);
+        }
+    }
+
+    pub fn delete(&mut self, index: usize) {
+        self.weights.remove(index);
+        for row in self.weights.iter_mut() {
+            row.remove(index);
+        }
+    }
+
+    /// Gives the copy inserted after `index` the same incoming and outgoing weights.
+    pub fn duplicate(&mut self, index: usize) {
+        self.weights.insert(index + 1, self.weights[index].clone());
+        for row in self.weights.iter_mut() {
+            row.insert(index + 1, row[index]);
+        }
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.weights.swap(a, b);
+        for row in self.weights.iter_mut() {
+            row.swap(a, b);
+        }
+    }
+
+    /// A grid with one row per transform applied last and one column per transform applied
+    /// next. Returns whether anything changed.
+    pub fn ui(&mut self, ui: &mut Ui, labels: &[String]) -> bool {
+        let mut changed = ui
+            .checkbox(&mut self.enabled, "Use transition matrix")
+            .changed();
+        if !self.enabled {
+            return changed;
+        }
+        self.resize(labels.len());
+        ui.label("Rows are the transform just applied, columns the one that may follow.");
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            egui::Grid::new("transition_matrix")
+                .num_columns(labels.len() + 1)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("from \\ to");
+                    for (to, label) in labels.iter().enumerate() {
+                        ui.label(to.to_string()).on_hover_text(label);
+                    }
+                    ui.end_row();
+                    for (from, row) in self.weights.iter_mut().enumerate() {
+                        ui.label(from.to_string()).on_hover_text(&labels[from]);
+                        for weight in row.iter_mut() {
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(weight)
+                                        .speed(0.01)
+                                        .clamp_range(0.0..=f32::MAX),
+                                )
+                                .changed();
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+        if ui.button("Reset to all ones").clicked() {
+            self.weights.clear();
+            self.resize(labels.len());
+            changed = true;
+        }
+        changed
+    }
+}
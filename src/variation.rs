@@ -0,0 +1,200 @@
+use std::f32::consts::PI;
+
+use barnsley::util::Point;
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+/// The most parameters any variation takes.
+pub const MAX_PARAMETERS: usize = 4;
+
+/// The classic nonlinear variations from Draves and Reckase's "The Fractal Flame Algorithm".
+///
+/// Most are fixed maps of the plane. The parametric ones read their parameters, named by
+/// [`Variation::parameter_names`], from the front of a `[f32; MAX_PARAMETERS]`; where the
+/// paper takes them from the transform's affine coefficients they are free parameters here.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, EnumIter, Serialize, Deserialize)]
+pub enum Variation {
+    #[default]
+    Linear,
+    Sinusoidal,
+    Spherical,
+    Swirl,
+    Horseshoe,
+    Polar,
+    Handkerchief,
+    Heart,
+    Disc,
+    Spiral,
+    Hyperbolic,
+    Diamond,
+    Ex,
+    Bent,
+    Fisheye,
+    Popcorn,
+    Exponential,
+    Power,
+    Cosine,
+    Rings,
+    Fan,
+    Blob,
+    Pdj,
+    Eyefish,
+    Bubble,
+    Cylinder,
+    Perspective,
+    Tangent,
+    Cross,
+}
+
+impl Variation {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Variation::Linear => "Linear",
+            Variation::Sinusoidal => "Sinusoidal",
+            Variation::Spherical => "Spherical",
+            Variation::Swirl => "Swirl",
+            Variation::Horseshoe => "Horseshoe",
+            Variation::Polar => "Polar",
+            Variation::Handkerchief => "Handkerchief",
+            Variation::Heart => "Heart",
+            Variation::Disc => "Disc",
+            Variation::Spiral => "Spiral",
+            Variation::Hyperbolic => "Hyperbolic",
+            Variation::Diamond => "Diamond",
+            Variation::Ex => "Ex",
+            Variation::Bent => "Bent",
+            Variation::Fisheye => "Fisheye",
+            Variation::Popcorn => "Popcorn",
+            Variation::Exponential => "Exponential",
+            Variation::Power => "Power",
+            Variation::Cosine => "Cosine",
+            Variation::Rings => "Rings",
+            Variation::Fan => "Fan",
+            Variation::Blob => "Blob",
+            Variation::Pdj => "PDJ",
+            Variation::Eyefish => "Eyefish",
+            Variation::Bubble => "Bubble",
+            Variation::Cylinder => "Cylinder",
+            Variation::Perspective => "Perspective",
+            Variation::Tangent => "Tangent",
+            Variation::Cross => "Cross",
+        }
+    }
+
+    pub fn parameter_names(&self) -> &'static [&'static str] {
+        match self {
+            Variation::Popcorn | Variation::Fan => &["c", "f"],
+            Variation::Rings => &["c"],
+            Variation::Blob => &["high", "low", "waves"],
+            Variation::Pdj => &["a", "b", "c", "d"],
+            Variation::Perspective => &["angle", "distance"],
+            _ => &[],
+        }
+    }
+
+    /// Parameters that give a recognisable shape when the variation is first added.
+    pub fn default_parameters(&self) -> [f32; MAX_PARAMETERS] {
+        match self {
+            Variation::Popcorn => [0.1, 0.1, 0.0, 0.0],
+            Variation::Fan => [0.5, 0.2, 0.0, 0.0],
+            Variation::Rings => [0.5, 0.0, 0.0, 0.0],
+            Variation::Blob => [1.0, 0.5, 4.0, 0.0],
+            Variation::Pdj => [1.1, -1.3, 1.5, -1.9],
+            Variation::Perspective => [0.6, 2.0, 0.0, 0.0],
+            _ => [0.0; MAX_PARAMETERS],
+        }
+    }
+
+    /// Applies the variation. Points where it is undefined come out non-finite and are
+    /// dropped by the renderer.
+    pub fn apply(&self, point: Point, parameters: &[f32; MAX_PARAMETERS]) -> Point {
+        let Point { x, y } = point;
+        let r2 = x * x + y * y;
+        let r = r2.sqrt();
+        // The paper measures theta from the y axis, so sin(theta) = x / r.
+        let theta = x.atan2(y);
+        let [p0, p1, p2, p3] = *parameters;
+        let (px, py) = match self {
+            Variation::Linear => (x, y),
+            Variation::Sinusoidal => (x.sin(), y.sin()),
+            Variation::Spherical => (x / r2, y / r2),
+            Variation::Swirl => {
+                let (sin, cos) = r2.sin_cos();
+                (x * sin - y * cos, x * cos + y * sin)
+            }
+            Variation::Horseshoe => ((x - y) * (x + y) / r, 2.0 * x * y / r),
+            Variation::Polar => (theta / PI, r - 1.0),
+            Variation::Handkerchief => (r * (theta + r).sin(), r * (theta - r).cos()),
+            Variation::Heart => (r * (theta * r).sin(), -r * (theta * r).cos()),
+            Variation::Disc => {
+                let (sin, cos) = (PI * r).sin_cos();
+                (theta / PI * sin, theta / PI * cos)
+            }
+            Variation::Spiral => ((theta.cos() + r.sin()) / r, (theta.sin() - r.cos()) / r),
+            Variation::Hyperbolic => (theta.sin() / r, r * theta.cos()),
+            Variation::Diamond => (theta.sin() * r.cos(), theta.cos() * r.sin()),
+            Variation::Ex => {
+                let a = (theta + r).sin().powi(3);
+                let b = (theta - r).cos().powi(3);
+                (r * (a + b), r * (a - b))
+            }
+            Variation::Bent => match (x >= 0.0, y >= 0.0) {
+                (true, true) => (x, y),
+                (false, true) => (2.0 * x, y),
+                (true, false) => (x, y / 2.0),
+                (false, false) => (2.0 * x, y / 2.0),
+            },
+            Variation::Fisheye => (2.0 * y / (r + 1.0), 2.0 * x / (r + 1.0)),
+            Variation::Popcorn => (
+                x + p0 * (3.0 * y).tan().sin(),
+                y + p1 * (3.0 * x).tan().sin(),
+            ),
+            Variation::Exponential => {
+                let (sin, cos) = (PI * y).sin_cos();
+                let scale = (x - 1.0).exp();
+                (scale * cos, scale * sin)
+            }
+            Variation::Power => {
+                let scale = r.powf(theta.sin());
+                (scale * theta.cos(), scale * theta.sin())
+            }
+            Variation::Cosine => (
+                (PI * x).cos() * y.cosh(),
+                -(PI * x).sin() * y.sinh(),
+            ),
+            Variation::Rings => {
+                let k = p0 * p0;
+                let scale = (r + k).rem_euclid(2.0 * k) - k + r * (1.0 - k);
+                (scale * theta.cos(), scale * theta.sin())
+            }
+            Variation::Fan => {
+                let t = PI * p0 * p0;
+                let angle = if (theta + p1).rem_euclid(t) > t / 2.0 {
+                    theta - t / 2.0
+                } else {
+                    theta + t / 2.0
+                };
+                (r * angle.cos(), r * angle.sin())
+            }
+            Variation::Blob => {
+                let scale = r * (p1 + (p0 - p1) / 2.0 * ((p2 * theta).sin() + 1.0));
+                (scale * theta.cos(), scale * theta.sin())
+            }
+            Variation::Pdj => ((p0 * y).sin() - (p1 * x).cos(), (p2 * x).sin() - (p3 * y).cos()),
+            Variation::Eyefish => (2.0 * x / (r + 1.0), 2.0 * y / (r + 1.0)),
+            Variation::Bubble => (4.0 * x / (r2 + 4.0), 4.0 * y / (r2 + 4.0)),
+            Variation::Cylinder => (x.sin(), y),
+            Variation::Perspective => {
+                let (sin, cos) = p0.sin_cos();
+                let scale = p1 / (p1 - y * sin);
+                (scale * x, scale * y * cos)
+            }
+            Variation::Tangent => (x.sin() / y.cos(), y.tan()),
+            Variation::Cross => {
+                let scale = 1.0 / (x * x - y * y).abs();
+                (scale * x, scale * y)
+            }
+        };
+        Point { x: px, y: py }
+    }
+}